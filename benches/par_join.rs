@@ -0,0 +1,79 @@
+extern crate criterion;
+extern crate nalgebra as na;
+extern crate nalgebra_glm as glm;
+
+#[path = "../src/ecs/custom.rs"]
+mod custom;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use custom::*;
+use rayon::prelude::*;
+
+const ENTITIES: u32 = 10_000;
+
+fn calculate_model_matrix(
+    pos: &na::Point3<f32>,
+    rot: &na::UnitQuaternion<f32>,
+    scale: f32,
+) -> glm::Mat4 {
+    glm::translation(&pos.coords) * rot.to_homogeneous() * glm::scaling(&glm::Vec3::repeat(scale))
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut entities = EntitiesStorage::new();
+    let mut positions = ComponentStorage::<na::Point3<f32>>::new();
+    let mut rotations = ComponentStorage::<na::UnitQuaternion<f32>>::new();
+    let mut scales = ComponentStorage::<f32>::new();
+    let mut model_matrices = ComponentStorage::<glm::Mat4>::new();
+    for entity in entities.allocate_many(ENTITIES) {
+        let x = entity.index as f32;
+        positions.insert(entity, na::Point3::new(x, x * 0.5, -x));
+        rotations.insert(
+            entity,
+            na::UnitQuaternion::from_axis_angle(&na::Vector3::y_axis(), x / 100.0),
+        );
+        scales.insert(entity, 1.0 + x / ENTITIES as f32);
+    }
+    model_matrices.replace_mask_with(
+        &(&entities, &positions, &rotations, &scales).join_mask(),
+        glm::Mat4::identity,
+    );
+
+    c.bench_function("join model matrices", |b| {
+        b.iter(|| {
+            for (pos, rot, scale, model_matrix) in
+                join((&positions, &rotations, &scales, &mut model_matrices))
+            {
+                *model_matrix = calculate_model_matrix(pos, rot, *scale);
+            }
+            black_box(&model_matrices);
+        })
+    });
+
+    c.bench_function("par_join model matrices", |b| {
+        b.iter(|| {
+            par_join((&positions, &rotations, &scales, &mut model_matrices)).for_each(
+                |(pos, rot, scale, model_matrix)| {
+                    *model_matrix = calculate_model_matrix(pos, rot, *scale);
+                },
+            );
+            black_box(&model_matrices);
+        })
+    });
+
+    // fetches on the calling thread, the baseline for par_join fetching inside the workers
+    c.bench_function("collected join model matrices", |b| {
+        b.iter(|| {
+            join((&positions, &rotations, &scales, &mut model_matrices))
+                .collect::<Vec<_>>()
+                .into_par_iter()
+                .for_each(|(pos, rot, scale, model_matrix)| {
+                    *model_matrix = calculate_model_matrix(pos, rot, *scale);
+                });
+            black_box(&model_matrices);
+        })
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -11,6 +11,8 @@ fn main() {
     let shaders = [
         "debug_aabb.frag",
         "debug_aabb.vert",
+        "debug_gizmo.frag",
+        "debug_gizmo.vert",
         "depth_prepass.vert",
         "generate_work.comp",
         "gltf_mesh.frag",
@@ -0,0 +1,329 @@
+use super::{systems::InputState, world::World};
+use hashbrown::HashSet;
+use imgui::im_str;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+use winit::event::{ButtonId, VirtualKeyCode};
+
+/// Loaded on startup if it exists, written by the debug GUI
+pub const KEY_BINDINGS_PATH: &str = "key_bindings.json";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    MoveFast,
+    ToggleFly,
+    CycleCamera,
+    Fire,
+    Select,
+    Quit,
+}
+
+const ACTIONS: [Action; 12] = [
+    Action::MoveForward,
+    Action::MoveBackward,
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::MoveUp,
+    Action::MoveDown,
+    Action::MoveFast,
+    Action::ToggleFly,
+    Action::CycleCamera,
+    Action::Fire,
+    Action::Select,
+    Action::Quit,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Button(ButtonId),
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{:?}", key),
+            Binding::Button(button) => write!(f, "Mouse {}", button),
+        }
+    }
+}
+
+/// Keys and mouse buttons bound to each `Action`, saved as JSON. Actions can have any number
+/// of bindings, unbound ones can't be triggered.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyBindings {
+    bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> KeyBindings {
+        let bindings = vec![
+            (Action::MoveForward, Binding::Key(VirtualKeyCode::W)),
+            (Action::MoveBackward, Binding::Key(VirtualKeyCode::S)),
+            (Action::MoveLeft, Binding::Key(VirtualKeyCode::A)),
+            (Action::MoveRight, Binding::Key(VirtualKeyCode::D)),
+            (Action::MoveUp, Binding::Key(VirtualKeyCode::Space)),
+            (Action::MoveDown, Binding::Key(VirtualKeyCode::LControl)),
+            (Action::MoveFast, Binding::Key(VirtualKeyCode::LShift)),
+            (Action::ToggleFly, Binding::Key(VirtualKeyCode::G)),
+            (Action::CycleCamera, Binding::Key(VirtualKeyCode::C)),
+            // firing needs fly mode and selecting needs it off, so they can share a button
+            (Action::Fire, Binding::Button(1)),
+            (Action::Select, Binding::Button(1)),
+            (Action::Quit, Binding::Key(VirtualKeyCode::Escape)),
+        ];
+        KeyBindings {
+            bindings: bindings
+                .into_iter()
+                .map(|(action, binding)| (action, vec![binding]))
+                .collect(),
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Falls back to the default bindings if there is no file at `path`
+    pub fn open_or_default(path: &str) -> KeyBindings {
+        if !Path::new(path).exists() {
+            return KeyBindings::default();
+        }
+        let file = File::open(path)
+            .unwrap_or_else(|err| panic!("failed to open key bindings {}: {}", path, err));
+        serde_json::from_reader(BufReader::new(file))
+            .unwrap_or_else(|err| panic!("failed to parse key bindings {}: {}", path, err))
+    }
+
+    pub fn save(&self, path: &str) {
+        let file = File::create(path)
+            .unwrap_or_else(|err| panic!("failed to create key bindings {}: {}", path, err));
+        serde_json::to_writer_pretty(BufWriter::new(file), self)
+            .unwrap_or_else(|err| panic!("failed to write key bindings {}: {}", path, err));
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[][..], Vec::as_slice)
+    }
+
+    /// Replaces the bindings of `action`
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        self.bindings.insert(action, vec![binding]);
+    }
+
+    fn actions(&self, binding: Binding) -> impl Iterator<Item = Action> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |(_, bindings)| bindings.contains(&binding))
+            .map(|(action, _)| *action)
+    }
+
+    /// Bindings of `action` joined for display, like "W, Up"
+    pub fn describe(&self, action: Action) -> String {
+        let names: Vec<String> = self
+            .bindings(action)
+            .iter()
+            .map(|b| b.to_string())
+            .collect();
+        names.join(", ")
+    }
+}
+
+fn pressed_bindings(input: &InputState) -> impl Iterator<Item = Binding> + '_ {
+    input
+        .key_presses
+        .iter()
+        .filter_map(|key| key.map(Binding::Key))
+        .chain(input.button_presses.iter().map(|b| Binding::Button(*b)))
+}
+
+fn released_bindings(input: &InputState) -> impl Iterator<Item = Binding> + '_ {
+    input
+        .key_releases
+        .iter()
+        .filter_map(|key| key.map(Binding::Key))
+        .chain(input.button_releases.iter().map(|b| Binding::Button(*b)))
+}
+
+/// State of every `Action` during the frame, systems query this instead of the raw input
+#[derive(Default)]
+pub struct ActionState {
+    held: HashSet<Action>,
+    pressed: HashSet<Action>,
+    released: HashSet<Action>,
+}
+
+impl ActionState {
+    /// Triggered during this frame
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn released(&self, action: Action) -> bool {
+        self.released.contains(&action)
+    }
+
+    /// Pressed in this frame or an earlier one and not released since
+    pub fn held(&self, action: Action) -> bool {
+        self.held.contains(&action)
+    }
+}
+
+/// Translates the frame's `InputState` into actions, replayed input is mapped through the
+/// current bindings as well
+pub struct MapActions;
+
+impl MapActions {
+    pub fn exec(input: &InputState, key_bindings: &KeyBindings, actions: &mut ActionState) {
+        actions.pressed.clear();
+        actions.released.clear();
+        for binding in pressed_bindings(input) {
+            for action in key_bindings.actions(binding) {
+                actions.pressed.insert(action);
+                actions.held.insert(action);
+            }
+        }
+        for binding in released_bindings(input) {
+            for action in key_bindings.actions(binding) {
+                actions.released.insert(action);
+                actions.held.remove(&action);
+            }
+        }
+    }
+}
+
+/// Debug GUI section listing the bindings, each action can be rebound to the next key or
+/// mouse button pressed
+pub struct KeyBindingsEditor {
+    rebinding: Option<Action>,
+}
+
+impl KeyBindingsEditor {
+    pub fn new() -> KeyBindingsEditor {
+        KeyBindingsEditor { rebinding: None }
+    }
+
+    /// Rebinds the action waiting for it to the first key or mouse button pressed this frame.
+    /// Needs to run before `MapActions`, it consumes the presses of the frame so that they
+    /// don't also trigger the actions they are bound to.
+    pub fn capture(&mut self, input: &mut InputState, key_bindings: &mut KeyBindings) {
+        if let Some(action) = self.rebinding {
+            if let Some(binding) = pressed_bindings(input).next() {
+                key_bindings.rebind(action, binding);
+                self.rebinding = None;
+                input.key_presses.clear();
+                input.button_presses.clear();
+            }
+        }
+    }
+
+    pub fn draw(&mut self, ui: &imgui::Ui, world: &World) {
+        if !ui.collapsing_header(&im_str!("Key bindings")).build() {
+            return;
+        }
+        let mut key_bindings = world.write::<KeyBindings>();
+        for &action in ACTIONS.iter() {
+            if self.rebinding == Some(action) {
+                ui.text(&im_str!("{:?}: press a key or mouse button", action));
+                ui.same_line(0.0);
+                if ui.button(&im_str!("Cancel##{:?}", action), [0.0, 0.0]) {
+                    self.rebinding = None;
+                }
+            } else {
+                ui.text(&im_str!("{:?}: {}", action, key_bindings.describe(action)));
+                ui.same_line(0.0);
+                if ui.button(&im_str!("Rebind##{:?}", action), [0.0, 0.0]) {
+                    self.rebinding = Some(action);
+                }
+            }
+        }
+        if ui.button(&im_str!("Save key bindings"), [0.0, 0.0]) {
+            key_bindings.save(KEY_BINDINGS_PATH);
+        }
+        ui.same_line(0.0);
+        if ui.button(&im_str!("Reset to defaults"), [0.0, 0.0]) {
+            *key_bindings = KeyBindings::default();
+        }
+        ui.spacing();
+    }
+}
+
+#[test]
+fn test_map_actions() {
+    let mut key_bindings = KeyBindings::default();
+    let mut actions = ActionState::default();
+    let mut input = InputState {
+        key_presses: vec![Some(VirtualKeyCode::W), None],
+        button_presses: vec![1],
+        ..InputState::default()
+    };
+    MapActions::exec(&input, &key_bindings, &mut actions);
+    assert!(actions.pressed(Action::MoveForward));
+    assert!(actions.held(Action::MoveForward));
+    assert!(actions.pressed(Action::Fire));
+    assert!(!actions.pressed(Action::MoveBackward));
+
+    input.clear();
+    MapActions::exec(&input, &key_bindings, &mut actions);
+    assert!(!actions.pressed(Action::MoveForward));
+    assert!(actions.held(Action::MoveForward));
+
+    input.key_releases = vec![Some(VirtualKeyCode::W)];
+    MapActions::exec(&input, &key_bindings, &mut actions);
+    assert!(actions.released(Action::MoveForward));
+    assert!(!actions.held(Action::MoveForward));
+
+    key_bindings.rebind(Action::MoveForward, Binding::Key(VirtualKeyCode::Up));
+    input.clear();
+    input.key_presses = vec![Some(VirtualKeyCode::W), Some(VirtualKeyCode::Up)];
+    MapActions::exec(&input, &key_bindings, &mut actions);
+    assert!(actions.pressed(Action::MoveForward));
+    assert_eq!(key_bindings.describe(Action::MoveForward), "Up");
+    // the old key is unbound now
+    input.clear();
+    input.key_releases = vec![Some(VirtualKeyCode::W)];
+    MapActions::exec(&input, &key_bindings, &mut actions);
+    assert!(actions.held(Action::MoveForward));
+}
+
+#[test]
+fn test_capture_rebinding() {
+    let mut key_bindings = KeyBindings::default();
+    let mut actions = ActionState::default();
+    let mut editor = KeyBindingsEditor::new();
+    editor.rebinding = Some(Action::Fire);
+    let mut input = InputState {
+        key_presses: vec![Some(VirtualKeyCode::Escape)],
+        ..InputState::default()
+    };
+    editor.capture(&mut input, &mut key_bindings);
+    MapActions::exec(&input, &key_bindings, &mut actions);
+    assert_eq!(editor.rebinding, None);
+    assert_eq!(key_bindings.describe(Action::Fire), "Escape");
+    // the press only rebinds, neither its old action nor the new one trigger
+    assert!(!actions.pressed(Action::Quit));
+    assert!(!actions.pressed(Action::Fire));
+}
+
+#[test]
+fn test_key_bindings_round_trip() {
+    let mut key_bindings = KeyBindings::default();
+    key_bindings.rebind(Action::Fire, Binding::Key(VirtualKeyCode::F));
+    let serialized = serde_json::to_string(&key_bindings).unwrap();
+    let loaded: KeyBindings = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(loaded, key_bindings);
+    assert_eq!(
+        loaded.bindings(Action::Fire),
+        &[Binding::Key(VirtualKeyCode::F)]
+    );
+}
@@ -0,0 +1,248 @@
+use super::systems::{Camera, FrameTiming, FIXED_TIME_STEP};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+};
+
+/// Written when a camera path run ends, one line per frame
+pub const CAMERA_PATH_TIMINGS_PATH: &str = "camera_path_timings.csv";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CameraKeyframe {
+    /// Seconds since the start of the path
+    pub time: f32,
+    pub position: [f32; 3],
+    /// Quaternion coordinates in `[i, j, k, w]` order
+    pub rotation: [f32; 4],
+}
+
+/// Keyframed camera transforms, positions follow a Catmull-Rom spline through the keyframes and
+/// rotations are slerped between them
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct CameraPath {
+    pub keyframes: Vec<CameraKeyframe>,
+}
+
+impl CameraPath {
+    pub fn open(path: &str) -> CameraPath {
+        let file = File::open(path)
+            .unwrap_or_else(|err| panic!("failed to open camera path {}: {}", path, err));
+        let camera_path: CameraPath = serde_json::from_reader(BufReader::new(file))
+            .unwrap_or_else(|err| panic!("failed to parse camera path {}: {}", path, err));
+        assert!(
+            !camera_path.keyframes.is_empty(),
+            "camera path {} has no keyframes",
+            path
+        );
+        assert!(
+            camera_path
+                .keyframes
+                .windows(2)
+                .all(|pair| pair[0].time < pair[1].time),
+            "keyframe times in camera path {} are not increasing",
+            path
+        );
+        camera_path
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |last| last.time)
+    }
+
+    fn position(&self, ix: usize) -> na::Vector3<f32> {
+        na::Vector3::from(self.keyframes[ix].position)
+    }
+
+    fn rotation(&self, ix: usize) -> na::UnitQuaternion<f32> {
+        na::UnitQuaternion::from_quaternion(na::Quaternion::from(na::Vector4::from(
+            self.keyframes[ix].rotation,
+        )))
+    }
+
+    // Catmull-Rom tangent, one-sided at the ends
+    fn tangent(&self, ix: usize) -> na::Vector3<f32> {
+        let before = ix.saturating_sub(1);
+        let after = (ix + 1).min(self.keyframes.len() - 1);
+        if before == after {
+            return na::zero();
+        }
+        (self.position(after) - self.position(before))
+            / (self.keyframes[after].time - self.keyframes[before].time)
+    }
+
+    /// Clamped to the first and last keyframes outside of the path
+    pub fn sample(&self, time: f32) -> (na::Point3<f32>, na::UnitQuaternion<f32>) {
+        let next = match self.keyframes.iter().position(|k| k.time > time) {
+            Some(0) => 0,
+            Some(next) => next,
+            None => self.keyframes.len() - 1,
+        };
+        if next == 0 || time >= self.keyframes[next].time {
+            return (na::Point3::from(self.position(next)), self.rotation(next));
+        }
+        let previous = next - 1;
+        let span = self.keyframes[next].time - self.keyframes[previous].time;
+        let s = (time - self.keyframes[previous].time) / span;
+        // cubic Hermite basis
+        let (s2, s3) = (s * s, s * s * s);
+        let position = (2.0 * s3 - 3.0 * s2 + 1.0) * self.position(previous)
+            + (s3 - 2.0 * s2 + s) * span * self.tangent(previous)
+            + (-2.0 * s3 + 3.0 * s2) * self.position(next)
+            + (s3 - s2) * span * self.tangent(next);
+        let (from, to) = (self.rotation(previous), self.rotation(next));
+        let rotation = from.try_slerp(&to, s, 1.0e-6).unwrap_or(from);
+        (na::Point3::from(position), rotation)
+    }
+}
+
+/// Drives the `Camera` along a `CameraPath` in place of the camera controllers, advancing a
+/// fixed step per frame so that every run renders the same views. Records the duration of each
+/// frame on the way.
+pub struct CameraPathPlayback {
+    path: CameraPath,
+    frame: u32,
+    frame_times: Vec<f32>,
+}
+
+impl CameraPathPlayback {
+    pub fn new(path: CameraPath) -> CameraPathPlayback {
+        CameraPathPlayback {
+            path,
+            frame: 0,
+            frame_times: vec![],
+        }
+    }
+
+    /// Returns false once the end of the path was shown, leaving the camera untouched
+    pub fn exec(&mut self, frame_timing: &FrameTiming, camera: &mut Camera) -> bool {
+        let time = self.frame as f32 * FIXED_TIME_STEP;
+        // the previous frame is the first one rendered along the path
+        if self.frame > 0 {
+            self.frame_times.push(frame_timing.time_delta());
+        }
+        if time > self.path.duration() {
+            return false;
+        }
+        let (position, rotation) = self.path.sample(time);
+        camera.position = position;
+        camera.rotation = rotation;
+        self.frame += 1;
+        true
+    }
+
+    pub fn report(&self) -> TimingReport {
+        TimingReport::new(&self.frame_times)
+    }
+
+    /// Frame index and duration in milliseconds on each line
+    pub fn save_frame_times(&self, path: &str) {
+        let file = File::create(path)
+            .unwrap_or_else(|err| panic!("failed to create frame times {}: {}", path, err));
+        let mut writer = BufWriter::new(file);
+        for (frame, seconds) in self.frame_times.iter().enumerate() {
+            writeln!(writer, "{},{:.3}", frame, seconds * 1000.0)
+                .unwrap_or_else(|err| panic!("failed to write frame times {}: {}", path, err));
+        }
+    }
+}
+
+/// Summary of frame durations, in milliseconds
+#[derive(Debug, PartialEq)]
+pub struct TimingReport {
+    pub frames: usize,
+    pub mean: f32,
+    pub min: f32,
+    pub max: f32,
+    pub p50: f32,
+    pub p95: f32,
+    pub p99: f32,
+}
+
+impl TimingReport {
+    pub fn new(frame_times: &[f32]) -> TimingReport {
+        let mut sorted: Vec<f32> = frame_times.iter().map(|seconds| seconds * 1000.0).collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        // nearest rank
+        let percentile = |p: f32| {
+            if sorted.is_empty() {
+                return 0.0;
+            }
+            let rank = (p / 100.0 * sorted.len() as f32).ceil() as usize;
+            sorted[rank.max(1) - 1]
+        };
+        TimingReport {
+            frames: sorted.len(),
+            mean: sorted.iter().sum::<f32>() / sorted.len().max(1) as f32,
+            min: sorted.first().cloned().unwrap_or(0.0),
+            max: sorted.last().cloned().unwrap_or(0.0),
+            p50: percentile(50.0),
+            p95: percentile(95.0),
+            p99: percentile(99.0),
+        }
+    }
+}
+
+impl fmt::Display for TimingReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} frames, mean {:.2} ms, min {:.2} ms, max {:.2} ms, p50 {:.2} ms, p95 {:.2} ms, \
+             p99 {:.2} ms",
+            self.frames, self.mean, self.min, self.max, self.p50, self.p95, self.p99
+        )
+    }
+}
+
+#[cfg(test)]
+fn keyframe(time: f32, position: [f32; 3], yaw: f32) -> CameraKeyframe {
+    let rotation = na::UnitQuaternion::from_axis_angle(&na::Vector3::y_axis(), yaw);
+    let coords = rotation.into_inner().coords;
+    CameraKeyframe {
+        time,
+        position,
+        rotation: [coords.x, coords.y, coords.z, coords.w],
+    }
+}
+
+#[test]
+fn test_sample() {
+    let path = CameraPath {
+        keyframes: vec![
+            keyframe(0.0, [0.0, 0.0, 0.0], 0.0),
+            keyframe(1.0, [1.0, 0.0, 0.0], 1.0),
+            keyframe(2.0, [2.0, 0.0, 0.0], 1.0),
+            keyframe(4.0, [2.0, 2.0, 0.0], 0.0),
+        ],
+    };
+    // passes through every keyframe
+    for (ix, keyframe) in path.keyframes.iter().enumerate() {
+        let (position, rotation) = path.sample(keyframe.time);
+        assert_eq!(position.coords, path.position(ix));
+        assert!(rotation.angle_to(&path.rotation(ix)) < 1e-5);
+    }
+    // evenly spaced collinear keyframes give a straight line at constant speed
+    let (position, rotation) = path.sample(0.5);
+    assert!((position - na::Point3::new(0.5, 0.0, 0.0)).norm() < 1e-5);
+    assert!((rotation.angle() - 0.5).abs() < 1e-5);
+    let (_, rotation) = path.sample(1.5);
+    assert!((rotation.angle() - 1.0).abs() < 1e-5);
+    // clamped outside
+    assert_eq!(path.sample(-1.0).0, na::Point3::new(0.0, 0.0, 0.0));
+    assert_eq!(path.sample(10.0).0, na::Point3::new(2.0, 2.0, 0.0));
+}
+
+#[test]
+fn test_timing_report() {
+    let frame_times: Vec<f32> = (1..=100).map(|ms| ms as f32 / 1000.0).collect();
+    let report = TimingReport::new(&frame_times);
+    assert_eq!(report.frames, 100);
+    assert!((report.mean - 50.5).abs() < 1e-3);
+    assert!((report.min - 1.0).abs() < 1e-3);
+    assert!((report.max - 100.0).abs() < 1e-3);
+    assert!((report.p50 - 50.0).abs() < 1e-3);
+    assert!((report.p95 - 95.0).abs() < 1e-3);
+    assert!((report.p99 - 99.0).abs() < 1e-3);
+    assert_eq!(TimingReport::new(&[]).frames, 0);
+}
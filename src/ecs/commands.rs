@@ -0,0 +1,132 @@
+use super::{custom::*, world::World};
+use parking_lot::Mutex;
+use std::mem;
+
+type Command = Box<dyn FnOnce(&World) + Send>;
+
+/// Structural changes queued through a shared reference, so that systems doing them don't
+/// need to borrow the entities and storages mutably. Applied in queue order by
+/// `World::maintain()`, commands on entities that are dead by then are skipped.
+pub struct EntityCommands {
+    queue: Mutex<Vec<Command>>,
+}
+
+impl EntityCommands {
+    pub fn new() -> EntityCommands {
+        EntityCommands {
+            queue: Mutex::new(vec![]),
+        }
+    }
+
+    fn push<F: FnOnce(&World) + Send + 'static>(&self, command: F) {
+        self.queue.lock().push(Box::new(command));
+    }
+
+    /// Allocates the entity when applied and hands it over to `insert` for its components
+    pub fn spawn<F: FnOnce(&World, Entity) + Send + 'static>(&self, insert: F) {
+        self.push(move |world| {
+            let entity = world.entities_mut().allocate();
+            insert(world, entity);
+        });
+    }
+
+    pub fn insert<T: Send + Sync + 'static>(&self, entity: Entity, component: T) {
+        self.push(move |world| {
+            if world.entities().is_alive(entity) {
+                world.storage_mut::<T>().insert(entity, component);
+            }
+        });
+    }
+
+    /// Changes the component in place and marks it modified, skipped if the entity lost it
+    pub fn modify<T: Send + Sync + 'static, F: FnOnce(&mut T) + Send + 'static>(
+        &self,
+        entity: Entity,
+        f: F,
+    ) {
+        self.push(move |world| {
+            if world.entities().is_alive(entity) {
+                let mut storage = world.storage_mut::<T>();
+                if storage.mask().contains(entity.index) {
+                    f(storage.get_mut(entity).unwrap());
+                }
+            }
+        });
+    }
+
+    pub fn remove<T: Send + Sync + 'static>(&self, entity: Entity) {
+        self.push(move |world| {
+            if world.entities().is_alive(entity) {
+                world.storage_mut::<T>().entry(entity).remove();
+            }
+        });
+    }
+
+    pub fn despawn(&self, entity: Entity) {
+        self.push(move |world| {
+            let mut entities = world.entities_mut();
+            if entities.is_alive(entity) {
+                entities.remove(entity);
+            }
+        });
+    }
+
+    /// Commands queued while applying are applied as well
+    pub fn apply(&self, world: &World) {
+        loop {
+            let queued = mem::replace(&mut *self.queue.lock(), vec![]);
+            if queued.is_empty() {
+                break;
+            }
+            for command in queued {
+                command(world);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_commands() {
+    let mut world = World::new();
+    world.register::<u32>();
+    world.register::<f32>();
+    let existing = world.entities_mut().allocate();
+    world.storage_mut::<f32>().insert(existing, 1.0);
+    world.maintain();
+
+    let commands = world.commands();
+    commands.spawn(|world, entity| {
+        world.storage_mut::<u32>().insert(entity, 7);
+    });
+    commands.insert(existing, 3u32);
+    commands.modify::<f32, _>(existing, |value| *value += 1.0);
+    commands.remove::<f32>(existing);
+    commands.modify::<f32, _>(existing, |_| panic!("modified a removed component"));
+    assert!(world.storage::<u32>().mask().is_empty());
+
+    world.maintain();
+    assert_eq!(world.entities().mask().to_vec(), vec![0, 1]);
+    assert_eq!(*world.storage::<u32>().get(1).unwrap(), 7);
+    assert_eq!(*world.storage::<u32>().get(existing).unwrap(), 3);
+    assert!(world.storage::<f32>().mask().is_empty());
+    // applied before the change tracking frame starts, so they're seen during the next one
+    assert_eq!(world.storage::<u32>().inserted().to_vec(), vec![0, 1]);
+    assert_eq!(world.storage::<f32>().removed().to_vec(), vec![0]);
+
+    world.commands().despawn(existing);
+    world.commands().despawn(existing);
+    world.commands().insert(existing, 4u32);
+    assert_eq!(world.maintain().to_vec(), vec![existing.index]);
+    assert_eq!(world.entities().mask().to_vec(), vec![1]);
+    assert_eq!(world.storage::<u32>().removed().to_vec(), vec![0]);
+}
+
+#[test]
+fn test_nested_commands() {
+    let world = World::new();
+    world.commands().spawn(|world, _| {
+        world.commands().spawn(|_, _| ());
+    });
+    world.maintain();
+    assert_eq!(world.entities().mask().cardinality(), 2);
+}
@@ -1,3 +1,27 @@
+use super::custom::Entity;
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Light {
     pub strength: f32,
 }
+
+/// Where the projectile is headed, it is removed once it gets there or hits something
+pub struct ProjectileTarget(pub na::Point3<f32>);
+
+pub struct ProjectileVelocity(pub f32);
+
+/// Scale along the local axes, applied before the rotation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Scale(pub na::Vector3<f32>);
+
+/// Transform as of the previous simulation step, kept for entities moved by the simulation so
+/// that rendering can interpolate towards the current one
+pub struct PreviousTransform {
+    pub position: na::Point3<f32>,
+    pub rotation: na::UnitQuaternion<f32>,
+}
+
+/// Makes the position, rotation and scale of the entity relative to the model matrix of the
+/// parent. Entities whose parent is dead or has no model matrix are treated as roots.
+pub struct Parent(pub Entity);
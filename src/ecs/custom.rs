@@ -1,28 +1,95 @@
 #![allow(warnings)]
 
-use rayon;
+use rayon::{self, prelude::*};
 use std::{
-    borrow::BorrowMut,
-    collections::btree_map,
-    mem::swap,
+    any::Any,
+    borrow::{BorrowMut, Cow},
+    cell::UnsafeCell,
+    collections::{btree_map, VecDeque},
+    fmt,
+    mem::{self, swap},
     ops::{Deref, DerefMut},
 };
 
 use hashbrown::{hash_map, HashMap};
 
+/// Generational handle to an entity. The index slot gets reused after
+/// `EntitiesStorage::maintain()`, the generation tells the occupants apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Entity {
+    pub index: u32,
+    pub generation: u32,
+}
+
+/// Addresses an entity slot in the storages. Bare `u32` indices come from iterating
+/// bitmaps and are trusted to be current, `Entity` handles are checked for staleness.
+pub trait EntityRef: Copy + fmt::Debug {
+    fn index(self) -> u32;
+    fn generation(self) -> Option<u32>;
+}
+
+impl EntityRef for u32 {
+    fn index(self) -> u32 {
+        self
+    }
+
+    fn generation(self) -> Option<u32> {
+        None
+    }
+}
+
+impl EntityRef for Entity {
+    fn index(self) -> u32 {
+        self.index
+    }
+
+    fn generation(self) -> Option<u32> {
+        Some(self.generation)
+    }
+}
+
+fn generation_of(generations: &[u32], ix: u32) -> u32 {
+    generations.get(ix as usize).cloned().unwrap_or(0)
+}
+
+// Bumps the generation of every freed slot, so that handles to the previous occupant go stale
+fn advance_generations(generations: &mut Vec<u32>, freed: &croaring::Bitmap) {
+    if let Some(max) = freed.maximum() {
+        if generations.len() <= max as usize {
+            generations.resize(max as usize + 1, 0);
+        }
+    }
+    for ix in freed.iter() {
+        generations[ix as usize] = generations[ix as usize].wrapping_add(1);
+    }
+}
+
+fn check_generation<E: EntityRef>(generations: &[u32], entity: E) -> u32 {
+    let ix = entity.index();
+    if let Some(generation) = entity.generation() {
+        assert_eq!(
+            generation_of(generations, ix),
+            generation,
+            "stale entity handle {:?}",
+            entity
+        );
+    }
+    ix
+}
+
 pub struct EntitiesStorage {
     mask: croaring::Bitmap,
     deleted: croaring::Bitmap,
+    generations: Vec<u32>,
 }
 
-const MAX_ENTITIES: u32 = 512;
-
 impl EntitiesStorage {
     pub fn new() -> EntitiesStorage {
         let mut mask = croaring::Bitmap::create();
         EntitiesStorage {
             mask: mask,
             deleted: croaring::Bitmap::create(),
+            generations: vec![],
         }
     }
 
@@ -30,54 +97,150 @@ impl EntitiesStorage {
         &self.mask
     }
 
-    pub fn allocate(&mut self) -> u32 {
-        let free_slots = self.mask.flip(0..MAX_ENTITIES as u64) - &self.deleted;
-        let free = free_slots
-            .iter()
-            .next()
-            .expect("no space to allocate entities");
+    /// Current generation of the slot, live or not
+    pub fn generation(&self, ix: u32) -> u32 {
+        generation_of(&self.generations, ix)
+    }
+
+    /// Handle to the current occupant of the slot
+    pub fn entity(&self, ix: u32) -> Entity {
+        debug_assert!(
+            self.mask.contains(ix),
+            "EntitiesStorage::entity() on a dead slot"
+        );
+        Entity {
+            index: ix,
+            generation: self.generation(ix),
+        }
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.mask.contains(entity.index) && self.generation(entity.index) == entity.generation
+    }
+
+    // Lowest n slots that are neither alive nor waiting for maintain(), growing past the
+    // highest used id when the holes run out
+    fn free_slots(&self, n: u32) -> Vec<u32> {
+        let used_max = match (self.mask.maximum(), self.deleted.maximum()) {
+            (Some(a), Some(b)) => a.max(b) as u64 + 1,
+            (Some(a), None) | (None, Some(a)) => a as u64 + 1,
+            (None, None) => 0,
+        };
+        let free_slots = self.mask.flip(0..used_max + n as u64) - &self.deleted;
+        free_slots.iter().take(n as usize).collect()
+    }
+
+    pub fn allocate(&mut self) -> Entity {
+        let free = self.free_slots(1)[0];
         self.mask.add(free);
-        free
+        self.entity(free)
     }
 
-    pub fn allocate_many(&mut self, n: u32) -> Vec<u32> {
-        let free_slots = self.mask.flip(0..MAX_ENTITIES as u64) - &self.deleted;
-        let free_ids = free_slots.iter().take(n as usize).collect::<Vec<u32>>();
+    pub fn allocate_many(&mut self, n: u32) -> Vec<Entity> {
+        let free_ids = self.free_slots(n);
         self.mask.add_many(&free_ids);
-        free_ids
+        free_ids.into_iter().map(|ix| self.entity(ix)).collect()
     }
 
     pub fn allocate_mask(&mut self, n: u32) -> croaring::Bitmap {
-        let free_slots = self.mask.flip(0..MAX_ENTITIES as u64) - &self.deleted;
-        let free_ids = free_slots.iter().take(n as usize).collect::<Vec<u32>>();
+        let free_ids = self.free_slots(n);
         let mut mask = croaring::Bitmap::create();
         mask.add_many(&free_ids);
         self.mask.or_inplace(&mask);
         mask
     }
 
-    pub fn remove(&mut self, ix: u32) {
+    /// Brings specific slots back to life, e.g. when restoring a snapshot
+    pub fn allocate_ids(&mut self, ids: &croaring::Bitmap) {
+        assert_eq!(
+            self.mask.and_cardinality(ids) + self.deleted.and_cardinality(ids),
+            0,
+            "EntitiesStorage::allocate_ids() on slots that are not free"
+        );
+        self.mask.or_inplace(ids);
+    }
+
+    pub fn remove<E: EntityRef>(&mut self, entity: E) {
+        let ix = check_generation(&self.generations, entity);
+        debug_assert!(self.mask.contains(ix), "removing dead entity {:?}", entity);
         self.mask.remove(ix);
         self.deleted.add(ix);
     }
 
+    /// Frees the slots removed since the last call and returns them. Every `ComponentStorage`
+    /// needs to be maintained with the result to keep its generations in sync.
     pub fn maintain(&mut self) -> croaring::Bitmap {
         let mut x = croaring::Bitmap::create();
         swap(&mut self.deleted, &mut x);
+        advance_generations(&mut self.generations, &x);
         x
     }
 }
 
+// Per-frame bookkeeping of a ComponentStorage, cleared in clear_changes()
+struct Changes {
+    modified: croaring::Bitmap,
+    inserted: croaring::Bitmap,
+    removed: croaring::Bitmap,
+}
+
+impl Changes {
+    fn new() -> Changes {
+        Changes {
+            modified: croaring::Bitmap::create(),
+            inserted: croaring::Bitmap::create(),
+            removed: croaring::Bitmap::create(),
+        }
+    }
+
+    fn record_insert(&mut self, ix: u32, replaced: bool) {
+        // replacing a component inserted this frame is still just an insertion
+        if replaced && !self.inserted.contains(ix) {
+            self.removed.add(ix);
+        }
+        self.inserted.add(ix);
+        self.modified.add(ix);
+    }
+
+    fn record_remove(&mut self, ix: u32) {
+        // removing a component inserted this frame cancels the insertion out
+        if !self.inserted.contains(ix) {
+            self.removed.add(ix);
+        }
+        self.inserted.remove(ix);
+        self.modified.remove(ix);
+    }
+}
+
 pub struct ComponentStorage<T> {
     mask: croaring::Bitmap,
-    data: HashMap<u32, T>,
+    // cells so that par_join() can hand out disjoint components from a shared map
+    data: HashMap<u32, UnsafeCell<T>>,
+    // mirrors EntitiesStorage, seeded in for_entities() and advanced in maintain()
+    generations: Vec<u32>,
+    changes: Changes,
 }
 
+// components are only written through `&mut self`, or by the workers of a `par_join()` that
+// borrows the storage mutably, so shared borrows just read them
+unsafe impl<T: Sync> Sync for ComponentStorage<T> {}
+
 impl<T> ComponentStorage<T> {
     pub fn new() -> ComponentStorage<T> {
         ComponentStorage {
             mask: croaring::Bitmap::create(),
             data: HashMap::new(),
+            generations: vec![],
+            changes: Changes::new(),
+        }
+    }
+
+    /// Empty storage starting from the current generations of `entities`, storages created
+    /// after entities were freed need this to accept their handles
+    pub fn for_entities(entities: &EntitiesStorage) -> ComponentStorage<T> {
+        ComponentStorage {
+            generations: entities.generations.clone(),
+            ..ComponentStorage::new()
         }
     }
 
@@ -85,6 +248,27 @@ impl<T> ComponentStorage<T> {
         &self.mask
     }
 
+    /// Components inserted or borrowed mutably since the last `clear_changes()`
+    pub fn modified(&self) -> &croaring::Bitmap {
+        &self.changes.modified
+    }
+
+    /// Components inserted since the last `clear_changes()`. A replaced component shows up
+    /// here and in `removed()`, so handle removals first.
+    pub fn inserted(&self) -> &croaring::Bitmap {
+        &self.changes.inserted
+    }
+
+    /// Components removed since the last `clear_changes()`, including the ones dropped
+    /// by `maintain()`
+    pub fn removed(&self) -> &croaring::Bitmap {
+        &self.changes.removed
+    }
+
+    pub fn clear_changes(&mut self) {
+        self.changes = Changes::new();
+    }
+
     pub fn allocate_mask(&mut self, mask: &croaring::Bitmap) {
         debug_assert_eq!(
             self.mask.and_cardinality(mask),
@@ -95,10 +279,30 @@ impl<T> ComponentStorage<T> {
     }
 
     pub fn replace_mask(&mut self, mask: &croaring::Bitmap) {
+        self.replace_mask_taking(mask, drop);
+    }
+
+    /// Like `replace_mask()`, but hands the removed components to `f` instead of dropping them
+    pub fn replace_mask_taking<F: FnMut(T)>(&mut self, mask: &croaring::Bitmap, mut f: F) {
         let diff = self.mask.andnot(mask);
         self.mask.clone_from(mask);
         for ix in diff.iter() {
-            self.data.remove(&ix);
+            if let Some(component) = self.data.remove(&ix) {
+                self.changes.record_remove(ix);
+                f(component.into_inner());
+            }
+        }
+    }
+
+    /// Like `replace_mask()`, but also fills in missing components so that the storage
+    /// can be joined mutably
+    pub fn replace_mask_with<F: Fn() -> T>(&mut self, mask: &croaring::Bitmap, fallback: F) {
+        self.replace_mask(mask);
+        for ix in mask.iter() {
+            if let hash_map::Entry::Vacant(slot) = self.data.entry(ix) {
+                slot.insert(UnsafeCell::new(fallback()));
+                self.changes.record_insert(ix, false);
+            }
         }
     }
 
@@ -115,36 +319,67 @@ impl<T> ComponentStorage<T> {
         self.mask.add_many(ixes);
     }
 
-    pub fn get<'a>(&'a self, ix: u32) -> Option<&'a T> {
+    pub fn get<'a, E: EntityRef>(&'a self, entity: E) -> Option<&'a T> {
+        let ix = check_generation(&self.generations, entity);
         debug_assert!(self.mask.contains(ix), "fetching dead component");
-        self.data.get(&ix)
+        // only par_join() writes through the cells, and it borrows the storage mutably
+        self.data.get(&ix).map(|cell| unsafe { &*cell.get() })
+    }
+
+    pub fn get_mut<'a, E: EntityRef>(&'a mut self, entity: E) -> Option<&'a mut T> {
+        let ix = check_generation(&self.generations, entity);
+        debug_assert!(self.mask.contains(ix), "fetching dead component");
+        let component = self.data.get_mut(&ix).map(UnsafeCell::get_mut);
+        if component.is_some() {
+            self.changes.modified.add(ix);
+        }
+        component
     }
 
-    pub fn entry<'a>(&'a mut self, ix: u32) -> ComponentEntry<'a, T> {
+    pub fn entry<'a, E: EntityRef>(&'a mut self, entity: E) -> ComponentEntry<'a, T> {
+        let ix = check_generation(&self.generations, entity);
         ComponentEntry {
             key: ix,
             btree_entry: self.data.entry(ix),
             mask: &mut self.mask,
+            changes: &mut self.changes,
         }
     }
 
-    pub fn insert<'a>(&'a mut self, ix: u32, val: T) -> Option<T> {
+    pub fn insert<'a, E: EntityRef>(&'a mut self, entity: E, val: T) -> Option<T> {
+        let ix = check_generation(&self.generations, entity);
         self.mask.add(ix);
-        self.data.insert(ix, val)
+        let previous = self
+            .data
+            .insert(ix, UnsafeCell::new(val))
+            .map(UnsafeCell::into_inner);
+        self.changes.record_insert(ix, previous.is_some());
+        previous
     }
 
     pub fn maintain(&mut self, freed: &croaring::Bitmap) {
+        self.maintain_with(freed, drop);
+    }
+
+    /// Like `maintain()`, but hands the components of the freed entities to `f` instead of
+    /// dropping them
+    pub fn maintain_with<F: FnMut(T)>(&mut self, freed: &croaring::Bitmap, mut f: F) {
         for x in freed.iter() {
-            self.data.remove(&x);
+            if let Some(component) = self.data.remove(&x) {
+                self.changes.record_remove(x);
+                f(component.into_inner());
+            }
         }
         self.mask.andnot_inplace(freed);
+        advance_generations(&mut self.generations, freed);
     }
 }
 
 pub struct ComponentEntry<'a, T> {
     key: u32,
-    btree_entry: hash_map::Entry<'a, u32, T, hash_map::DefaultHashBuilder>,
+    btree_entry: hash_map::Entry<'a, u32, UnsafeCell<T>, hash_map::DefaultHashBuilder>,
     mask: &'a mut croaring::Bitmap,
+    changes: &'a mut Changes,
 }
 
 impl<'a, T> ComponentEntry<'a, T> {
@@ -153,20 +388,28 @@ impl<'a, T> ComponentEntry<'a, T> {
         match self.btree_entry {
             hash_map::Entry::Occupied(slot) => {
                 slot.remove();
-                ()
+                self.changes.record_remove(self.key);
             }
             hash_map::Entry::Vacant(_) => (),
         }
     }
 
     pub fn or_insert(self, fallback: T) -> &'a mut T {
-        self.mask.add(self.key);
-        self.btree_entry.or_insert(fallback)
+        self.or_insert_with(|| fallback)
     }
 
     pub fn or_insert_with<F: FnOnce() -> T>(self, inserter: F) -> &'a mut T {
         self.mask.add(self.key);
-        self.btree_entry.or_insert_with(inserter)
+        match self.btree_entry {
+            hash_map::Entry::Occupied(slot) => {
+                self.changes.modified.add(self.key);
+                slot.into_mut().get_mut()
+            }
+            hash_map::Entry::Vacant(slot) => {
+                self.changes.record_insert(self.key, false);
+                slot.insert(UnsafeCell::new(inserter())).get_mut()
+            }
+        }
     }
 
     pub fn assume(self) -> &'a mut T {
@@ -174,9 +417,316 @@ impl<'a, T> ComponentEntry<'a, T> {
             self.mask.contains(self.key),
             "ComponentEntry::assume was wrong"
         );
+        self.changes.modified.add(self.key);
         // TODO: optimize with assume intrinsics for production builds
         self.btree_entry
             .or_insert_with(|| panic!("ComponentEntry::assume assumed no insertion was needed"))
+            .get_mut()
+    }
+}
+
+/// Anything that can take part in a `join()`. Storages borrowed immutably yield `&T`,
+/// borrowed mutably yield `&mut T` and mark the component modified. `EntitiesStorage` and
+/// plain bitmaps yield the entity id itself, a bitmap narrows the join down to its ids.
+pub trait Join {
+    type Item;
+
+    fn join_mask(&self) -> Cow<'_, croaring::Bitmap>;
+
+    /// Caller must guarantee that `ix` is in `join_mask()` and that it is fetched only once.
+    unsafe fn fetch(&mut self, ix: u32) -> Self::Item;
+
+    /// Raw handle that `par_join()` workers fetch through concurrently
+    type Shared: Copy + Send + Sync;
+
+    /// Hands out the handle for fetching the ids in `mask`, which mutable storages mark
+    /// modified up front since the workers can't touch the change tracking.
+    fn share(&mut self, mask: &croaring::Bitmap) -> Self::Shared;
+
+    /// Like `fetch()`, callers must also guarantee that `ix` is in the mask given to `share()`
+    /// and that no other thread fetches it.
+    unsafe fn fetch_shared(shared: Self::Shared, ix: u32) -> Self::Item;
+}
+
+/// Pointer to a storage borrowed by `par_join()`. Workers only look components up through it
+/// and `par_join()` requires the items they hand out to be `Send`, so sharing it between
+/// threads is sound.
+pub struct SharedStorage<S>(*const S);
+
+impl<S> Clone for SharedStorage<S> {
+    fn clone(&self) -> SharedStorage<S> {
+        SharedStorage(self.0)
+    }
+}
+
+impl<S> Copy for SharedStorage<S> {}
+
+unsafe impl<S> Send for SharedStorage<S> {}
+unsafe impl<S> Sync for SharedStorage<S> {}
+
+impl<'a> Join for &'a EntitiesStorage {
+    type Item = u32;
+
+    fn join_mask(&self) -> Cow<'_, croaring::Bitmap> {
+        Cow::Borrowed(&self.mask)
+    }
+
+    unsafe fn fetch(&mut self, ix: u32) -> u32 {
+        ix
+    }
+
+    type Shared = ();
+
+    fn share(&mut self, _mask: &croaring::Bitmap) {}
+
+    unsafe fn fetch_shared(_shared: (), ix: u32) -> u32 {
+        ix
+    }
+}
+
+impl<'a> Join for &'a croaring::Bitmap {
+    type Item = u32;
+
+    fn join_mask(&self) -> Cow<'_, croaring::Bitmap> {
+        Cow::Borrowed(*self)
+    }
+
+    unsafe fn fetch(&mut self, ix: u32) -> u32 {
+        ix
+    }
+
+    type Shared = ();
+
+    fn share(&mut self, _mask: &croaring::Bitmap) {}
+
+    unsafe fn fetch_shared(_shared: (), ix: u32) -> u32 {
+        ix
+    }
+}
+
+impl<'a, T> Join for &'a ComponentStorage<T> {
+    type Item = &'a T;
+
+    fn join_mask(&self) -> Cow<'_, croaring::Bitmap> {
+        Cow::Borrowed(&self.mask)
+    }
+
+    unsafe fn fetch(&mut self, ix: u32) -> &'a T {
+        let storage: &'a ComponentStorage<T> = *self;
+        let cell = storage
+            .data
+            .get(&ix)
+            .expect("Join::fetch() found no component for an id in the mask");
+        &*cell.get()
+    }
+
+    type Shared = SharedStorage<ComponentStorage<T>>;
+
+    fn share(&mut self, _mask: &croaring::Bitmap) -> Self::Shared {
+        SharedStorage(*self as *const ComponentStorage<T>)
+    }
+
+    unsafe fn fetch_shared(shared: Self::Shared, ix: u32) -> &'a T {
+        let cell = (*shared.0)
+            .data
+            .get(&ix)
+            .expect("Join::fetch_shared() found no component for an id in the mask");
+        &*cell.get()
+    }
+}
+
+impl<'a, T> Join for &'a mut ComponentStorage<T> {
+    type Item = &'a mut T;
+
+    fn join_mask(&self) -> Cow<'_, croaring::Bitmap> {
+        Cow::Borrowed(&self.mask)
+    }
+
+    unsafe fn fetch(&mut self, ix: u32) -> &'a mut T {
+        let component: *mut T = self
+            .data
+            .get_mut(&ix)
+            .expect("Join::fetch() found no component for an id in the mask")
+            .get_mut();
+        self.changes.modified.add(ix);
+        // every id is fetched at most once, so the returned borrows are disjoint
+        &mut *component
+    }
+
+    type Shared = SharedStorage<ComponentStorage<T>>;
+
+    fn share(&mut self, mask: &croaring::Bitmap) -> Self::Shared {
+        self.changes.modified.or_inplace(mask);
+        SharedStorage(&**self as *const ComponentStorage<T>)
+    }
+
+    unsafe fn fetch_shared(shared: Self::Shared, ix: u32) -> &'a mut T {
+        // the map is only read, the component is written through its cell and the ids of the
+        // workers are disjoint
+        let cell = (*shared.0)
+            .data
+            .get(&ix)
+            .expect("Join::fetch_shared() found no component for an id in the mask");
+        &mut *cell.get()
+    }
+}
+
+macro_rules! impl_join_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: Join),+> Join for ($($name,)+) {
+            type Item = ($($name::Item,)+);
+
+            #[allow(non_snake_case)]
+            fn join_mask(&self) -> Cow<'_, croaring::Bitmap> {
+                let ($(ref $name,)+) = *self;
+                let mut masks = vec![$($name.join_mask()),+].into_iter();
+                let mut mask = masks.next().unwrap().into_owned();
+                for other in masks {
+                    mask.and_inplace(&other);
+                }
+                Cow::Owned(mask)
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn fetch(&mut self, ix: u32) -> Self::Item {
+                let ($(ref mut $name,)+) = *self;
+                ($($name.fetch(ix),)+)
+            }
+
+            type Shared = ($($name::Shared,)+);
+
+            #[allow(non_snake_case)]
+            fn share(&mut self, mask: &croaring::Bitmap) -> Self::Shared {
+                let ($(ref mut $name,)+) = *self;
+                ($($name.share(mask),)+)
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn fetch_shared(shared: Self::Shared, ix: u32) -> Self::Item {
+                let ($($name,)+) = shared;
+                ($($name::fetch_shared($name, ix),)+)
+            }
+        }
+    };
+}
+
+impl_join_tuple!(A);
+impl_join_tuple!(A, B);
+impl_join_tuple!(A, B, C);
+impl_join_tuple!(A, B, C, D);
+impl_join_tuple!(A, B, C, D, E);
+impl_join_tuple!(A, B, C, D, E, F);
+impl_join_tuple!(A, B, C, D, E, F, G);
+impl_join_tuple!(A, B, C, D, E, F, G, H);
+
+/// Iterates the intersection of all the masks, computed once up front.
+pub struct JoinIter<J: Join> {
+    mask: croaring::Bitmap,
+    ids: std::vec::IntoIter<u32>,
+    storages: J,
+}
+
+impl<J: Join> JoinIter<J> {
+    /// The intersected mask, useful to `replace_mask()` an output storage with
+    pub fn mask(&self) -> &croaring::Bitmap {
+        &self.mask
+    }
+}
+
+impl<J: Join> Iterator for JoinIter<J> {
+    type Item = J::Item;
+
+    fn next(&mut self) -> Option<J::Item> {
+        let ix = self.ids.next()?;
+        Some(unsafe { self.storages.fetch(ix) })
+    }
+}
+
+pub fn join<J: Join>(storages: J) -> JoinIter<J> {
+    let mask = storages.join_mask().into_owned();
+    let ids = mask.to_vec().into_iter();
+    JoinIter {
+        mask,
+        ids,
+        storages,
+    }
+}
+
+/// Ids covered by one `par_join()` task
+const PAR_JOIN_CHUNK: u32 = 1024;
+
+/// Parallel `join()`. The masks are intersected once up front, then rayon splits the id range
+/// into chunks and every worker picks its ids out of the intersection and fetches their
+/// components itself.
+pub fn par_join<J: Join>(mut storages: J) -> impl ParallelIterator<Item = J::Item>
+where
+    J::Item: Send,
+{
+    let mask = storages.join_mask().into_owned();
+    let shared = storages.share(&mask);
+    let chunks = match (mask.minimum(), mask.maximum()) {
+        (Some(min), Some(max)) => min / PAR_JOIN_CHUNK..max / PAR_JOIN_CHUNK + 1,
+        _ => 0..0,
+    };
+    chunks.into_par_iter().flat_map(move |chunk| {
+        let start = u64::from(chunk * PAR_JOIN_CHUNK);
+        let mut ids = croaring::Bitmap::create();
+        ids.add_range(start..start + u64::from(PAR_JOIN_CHUNK));
+        ids.and_inplace(&mask);
+        ids.iter()
+            .map(|ix| unsafe { J::fetch_shared(shared, ix) })
+            .collect::<Vec<_>>()
+    })
+}
+
+/// Keeps removed components owning GPU resources alive until the frames that may still use
+/// them are done on the GPU.
+pub struct Graveyard {
+    // buried since the last collect()
+    fresh: Vec<Box<dyn Any + Send + Sync>>,
+    // with the timeline value that releases them, in increasing order
+    pending: VecDeque<(u64, Vec<Box<dyn Any + Send + Sync>>)>,
+}
+
+impl Graveyard {
+    pub fn new() -> Graveyard {
+        Graveyard {
+            fresh: vec![],
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn bury<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.fresh.push(Box::new(value));
+    }
+
+    /// Everything buried since the last call gets released once the timeline reaches
+    /// `release_at`, drops the batches released by the `reached` value.
+    pub fn collect(&mut self, release_at: u64, reached: u64) {
+        if !self.fresh.is_empty() {
+            self.pending
+                .push_back((release_at, mem::take(&mut self.fresh)));
+        }
+        while self
+            .pending
+            .front()
+            .map_or(false, |(release_at, _)| *release_at <= reached)
+        {
+            self.pending.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.fresh.len()
+            + self
+                .pending
+                .iter()
+                .map(|(_, batch)| batch.len())
+                .sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 }
 
@@ -221,7 +771,7 @@ fn test_no_reuse() {
     let first = entities.allocate();
     let second = entities.allocate();
     entities.remove(first);
-    assert_eq!(entities.allocate(), second + 1);
+    assert_eq!(entities.allocate().index, second.index + 1);
 }
 
 #[test]
@@ -230,8 +780,160 @@ fn test_reuse_after_maintain() {
     let first = entities.allocate();
     let second = entities.allocate();
     entities.remove(first);
-    assert_eq!(entities.maintain().to_vec(), vec![first]);
-    assert_eq!(entities.allocate(), first);
+    assert_eq!(entities.maintain().to_vec(), vec![first.index]);
+    let reused = entities.allocate();
+    assert_eq!(reused.index, first.index);
+    assert_eq!(reused.generation, first.generation + 1);
+    assert!(!entities.is_alive(first));
+    assert!(entities.is_alive(reused));
+}
+
+#[test]
+fn test_grow_on_demand() {
+    let mut entities = EntitiesStorage::new();
+    let ixes = entities.allocate_mask(1000);
+    assert_eq!(ixes.to_vec(), (0..1000).collect::<Vec<_>>());
+    entities.remove(10);
+    assert_eq!(entities.allocate().index, 1000);
+    entities.maintain();
+    assert_eq!(entities.allocate().index, 10);
+    assert_eq!(entities.allocate_many(2).len(), 2);
+    assert_eq!(entities.mask().cardinality(), 1003);
+}
+
+#[test]
+#[should_panic(expected = "stale entity handle")]
+fn test_stale_handle() {
+    let mut entities = EntitiesStorage::new();
+    let mut positions = ComponentStorage::<glm::Vec3>::new();
+    let first = entities.allocate();
+    positions.insert(first, glm::vec3(1.0, 0.0, 0.0));
+    entities.remove(first);
+    positions.maintain(&entities.maintain());
+    let reused = entities.allocate();
+    positions.insert(reused, glm::vec3(2.0, 0.0, 0.0));
+    positions.get(first);
+}
+
+#[test]
+fn test_join() {
+    let mut entities = EntitiesStorage::new();
+    let mut positions = ComponentStorage::<glm::Vec3>::new();
+    let mut velocities = ComponentStorage::<glm::Vec3>::new();
+    let ixes = entities.allocate_many(4);
+    for ix in &ixes {
+        positions.insert(*ix, glm::vec3(ix.index as f32, 0.0, 0.0));
+    }
+    velocities.insert(ixes[1], glm::vec3(0.0, 1.0, 0.0));
+    velocities.insert(ixes[3], glm::vec3(0.0, 2.0, 0.0));
+    entities.remove(ixes[3]);
+
+    let joined = join((&entities, &mut positions, &velocities));
+    assert_eq!(joined.mask().to_vec(), vec![1]);
+    for (_, position, velocity) in joined {
+        *position += velocity;
+    }
+    assert_eq!(positions.get(ixes[1]), Some(&glm::vec3(1.0, 1.0, 0.0)));
+    assert_eq!(positions.get(ixes[3]), Some(&glm::vec3(3.0, 0.0, 0.0)));
+}
+
+#[test]
+fn test_par_join() {
+    let mut entities = EntitiesStorage::new();
+    let mut positions = ComponentStorage::<glm::Vec3>::new();
+    let mut doubled = ComponentStorage::<glm::Vec3>::new();
+    for ix in entities.allocate_many(1000) {
+        positions.insert(ix, glm::vec3(ix.index as f32, 0.0, 0.0));
+    }
+    doubled.replace_mask_with(&(entities.mask() & positions.mask()), glm::Vec3::zeros);
+    par_join((&positions, &mut doubled)).for_each(|(position, doubled)| {
+        *doubled = position * 2.0;
+    });
+    assert_eq!(doubled.mask().cardinality(), 1000);
+    assert_eq!(doubled.get(999), Some(&glm::vec3(1998.0, 0.0, 0.0)));
+}
+
+#[test]
+fn test_par_join_sparse() {
+    let mut entities = EntitiesStorage::new();
+    let mut positions = ComponentStorage::<glm::Vec3>::new();
+    let ixes = entities.allocate_mask(10_000);
+    // spread over several chunks with gaps between them
+    let mut every_third = croaring::Bitmap::create();
+    for ix in ixes.iter().filter(|ix| ix % 3 == 0) {
+        every_third.add(ix);
+        positions.insert(ix, glm::Vec3::zeros());
+    }
+    positions.clear_changes();
+    let mut only = croaring::Bitmap::create();
+    only.add_range(2000..9000);
+    let visited: Vec<u32> = par_join((&only, &mut positions))
+        .map(|(ix, position)| {
+            position.x = ix as f32;
+            ix
+        })
+        .collect();
+    let expected = every_third.and(&only);
+    let mut sorted = visited.clone();
+    sorted.sort();
+    assert_eq!(sorted, expected.to_vec());
+    assert_eq!(positions.modified().to_vec(), expected.to_vec());
+    assert_eq!(positions.get(2001), Some(&glm::vec3(2001.0, 0.0, 0.0)));
+    assert_eq!(positions.get(9000), Some(&glm::Vec3::zeros()));
+}
+
+#[test]
+fn test_change_tracking() {
+    let mut entities = EntitiesStorage::new();
+    let mut positions = ComponentStorage::<glm::Vec3>::new();
+    let ixes = entities.allocate_many(4);
+    for ix in &ixes {
+        positions.insert(*ix, glm::Vec3::zeros());
+    }
+    assert_eq!(positions.modified().cardinality(), 4);
+    positions.clear_changes();
+
+    positions.get(ixes[0]);
+    for _ in join((&entities, &positions)) {}
+    assert!(positions.modified().is_empty());
+
+    *positions.get_mut(ixes[1]).unwrap() += glm::vec3(1.0, 0.0, 0.0);
+    *positions.entry(ixes[2]).assume() += glm::vec3(1.0, 0.0, 0.0);
+    let mut only = croaring::Bitmap::create();
+    only.add(ixes[3].index);
+    for (_, position) in join((&only, &mut positions)) {
+        *position += glm::vec3(1.0, 0.0, 0.0);
+    }
+    assert_eq!(positions.modified().to_vec(), vec![1, 2, 3]);
+
+    entities.remove(ixes[3]);
+    positions.maintain(&entities.maintain());
+    assert_eq!(positions.modified().to_vec(), vec![1, 2]);
+}
+
+#[test]
+fn test_insert_remove_events() {
+    let mut entities = EntitiesStorage::new();
+    let mut textures = ComponentStorage::<u32>::new();
+    let ixes = entities.allocate_many(4);
+    textures.insert(ixes[0], 0);
+    textures.insert(ixes[1], 1);
+    textures.clear_changes();
+
+    textures.insert(ixes[1], 10);
+    textures.insert(ixes[2], 2);
+    textures.insert(ixes[2], 20);
+    textures.insert(ixes[3], 3);
+    textures.entry(ixes[3]).remove();
+    textures.entry(ixes[0]).remove();
+    assert_eq!(textures.inserted().to_vec(), vec![1, 2]);
+    assert_eq!(textures.removed().to_vec(), vec![0, 1]);
+
+    textures.clear_changes();
+    entities.remove(ixes[2]);
+    textures.maintain(&entities.maintain());
+    assert!(textures.inserted().is_empty());
+    assert_eq!(textures.removed().to_vec(), vec![2]);
 }
 
 #[test]
@@ -242,7 +944,7 @@ fn test_components() {
     let timedelta = &mut 0.0f32;
 
     let ixes = entities.allocate_many(5);
-    positions.allocate_many(&[ixes[0], ixes[3], ixes[4], 8]);
+    positions.allocate_many(&[ixes[0].index, ixes[3].index, ixes[4].index, 8]);
 
     *timedelta = 3.0;
     rayon::join(
@@ -257,9 +959,9 @@ fn test_components() {
         },
     );
     for ix in (positions.mask() & velocity.mask() & entities.mask()).iter() {
-        *positions.entry(ix).or_insert(na::zero()) += velocity.data.get(&ix).unwrap() * *timedelta;
+        *positions.entry(ix).or_insert(na::zero()) += velocity.get(ix).unwrap() * *timedelta;
     }
 
     assert_eq!(*timedelta, 5.0);
-    assert_eq!(positions.data.get(&3), Some(&glm::vec3(50.0, 100.0, 0.0)));
+    assert_eq!(positions.get(3), Some(&glm::vec3(50.0, 100.0, 0.0)));
 }
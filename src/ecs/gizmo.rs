@@ -0,0 +1,632 @@
+use super::{
+    super::renderer::Swapchain,
+    bindings::{Action, ActionState},
+    components::{Parent, Scale},
+    custom::*,
+    hierarchy::parent_matrix,
+    systems::{Camera, InputState, MousePicking, RuntimeConfiguration},
+};
+use imgui::im_str;
+#[cfg(feature = "microprofile")]
+use microprofile::scope;
+use na::RealField;
+
+/// Length of the handles as a fraction of the distance to the camera, so that the gizmo keeps
+/// roughly the same size on screen
+const GIZMO_SIZE: f32 = 0.15;
+/// How close to a handle the cursor ray needs to pass, relative to the handle length
+const HANDLE_TOLERANCE: f32 = 0.06;
+/// Plane handles are squares spanning this range along both of their axes
+const PLANE_HANDLE: (f32, f32) = (0.2, 0.45);
+const RING_SEGMENTS: usize = 48;
+/// Keeps scaling from collapsing or mirroring the entity
+const MIN_SCALE_FACTOR: f32 = 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+/// Orientation of the handles, scale handles always follow the entity's own axes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GizmoSpace {
+    World,
+    Local,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GizmoHandle {
+    /// Moves or scales along the axis, rotates around it
+    Axis(usize),
+    /// Moves in the plane of the other two axes
+    Plane(usize),
+}
+
+/// Line segment of the gizmo in world space, drawn over everything else
+#[derive(Clone, Debug, PartialEq)]
+pub struct GizmoLine {
+    pub start: na::Point3<f32>,
+    pub end: na::Point3<f32>,
+    pub color: glm::Vec4,
+}
+
+/// Where the gizmo is drawn and which directions its handles point in
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Frame {
+    center: na::Point3<f32>,
+    axes: [na::Unit<na::Vector3<f32>>; 3],
+    size: f32,
+}
+
+/// State captured when a handle is grabbed, the drag is applied relative to it so that
+/// snapping doesn't accumulate errors
+struct Drag {
+    entity: Entity,
+    handle: GizmoHandle,
+    frame: Frame,
+    /// Where the cursor ray first hit the handle
+    grab: na::Point3<f32>,
+    /// Maps world space into the space of the entity's position, identity for roots
+    parent_inverse: glm::Mat4,
+    position: na::Point3<f32>,
+    rotation: na::UnitQuaternion<f32>,
+    scale: na::Vector3<f32>,
+}
+
+/// Translate, rotate and scale manipulator for the `Selected` entity, `ManipulateGizmo` drags
+/// its handles with `Action::Select` while the cursor is free. Snapping rounds the change since
+/// the handle was grabbed to the steps below.
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    pub space: GizmoSpace,
+    pub snap: bool,
+    /// In world units
+    pub grid: f32,
+    /// In degrees
+    pub angle: f32,
+    /// Step of the scale factor
+    pub scale_step: f32,
+    hovered: Option<GizmoHandle>,
+    drag: Option<Drag>,
+}
+
+impl Gizmo {
+    pub fn new() -> Gizmo {
+        Gizmo {
+            mode: GizmoMode::Translate,
+            space: GizmoSpace::World,
+            snap: false,
+            grid: 0.5,
+            angle: 15.0,
+            scale_step: 0.1,
+            hovered: None,
+            drag: None,
+        }
+    }
+
+    /// Whether a handle is held, the click shouldn't select anything else then
+    pub fn dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    fn frame(
+        &self,
+        camera: &Camera,
+        model_matrix: &glm::Mat4,
+        rotation: &na::UnitQuaternion<f32>,
+    ) -> Frame {
+        let center = na::Point3::from(model_matrix.column(3).xyz());
+        let local = self.space == GizmoSpace::Local || self.mode == GizmoMode::Scale;
+        let axis = |ix: usize| {
+            let world = [
+                na::Vector3::x_axis(),
+                na::Vector3::y_axis(),
+                na::Vector3::z_axis(),
+            ][ix];
+            if local {
+                // the entity's axes with parent rotations applied, falling back to its own
+                // rotation when the matrix is degenerate
+                na::Unit::try_new(model_matrix.column(ix).xyz(), 1.0e-6)
+                    .unwrap_or_else(|| rotation * world)
+            } else {
+                world
+            }
+        };
+        Frame {
+            center,
+            axes: [axis(0), axis(1), axis(2)],
+            size: GIZMO_SIZE * (center - camera.position).norm().max(1.0e-3),
+        }
+    }
+
+    /// The handle the ray passes over first, if any
+    fn hit_test(&self, frame: &Frame, ray: &ncollide3d::query::Ray<f32>) -> Option<GizmoHandle> {
+        let tolerance = HANDLE_TOLERANCE * frame.size;
+        let mut closest: Option<(GizmoHandle, f32)> = None;
+        let mut candidate = |handle: GizmoHandle, toi: f32| {
+            if closest.map_or(true, |(_, closest_toi)| toi < closest_toi) {
+                closest = Some((handle, toi));
+            }
+        };
+        for ix in 0..3 {
+            let axis = frame.axes[ix];
+            match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    let end = frame.center + frame.size * axis.into_inner();
+                    let (distance, toi) = ray_segment_distance(ray, &frame.center, &end);
+                    if distance < tolerance {
+                        candidate(GizmoHandle::Axis(ix), toi);
+                    }
+                }
+                GizmoMode::Rotate => {
+                    if let Some(toi) = ray_plane(ray, &frame.center, &axis) {
+                        let radius = (ray.point_at(toi) - frame.center).norm();
+                        if (radius - frame.size).abs() < tolerance {
+                            candidate(GizmoHandle::Axis(ix), toi);
+                        }
+                    }
+                }
+            }
+            if self.mode == GizmoMode::Translate {
+                if let Some(toi) = ray_plane(ray, &frame.center, &axis) {
+                    let offset = ray.point_at(toi) - frame.center;
+                    let (a, b) = other_axes(ix);
+                    let inside = |along: f32| {
+                        along >= PLANE_HANDLE.0 * frame.size && along <= PLANE_HANDLE.1 * frame.size
+                    };
+                    if inside(offset.dot(&frame.axes[a])) && inside(offset.dot(&frame.axes[b])) {
+                        candidate(GizmoHandle::Plane(ix), toi);
+                    }
+                }
+            }
+        }
+        closest.map(|(handle, _)| handle)
+    }
+
+    /// Point under the cursor on the surface the handle moves along
+    fn grab_point(
+        &self,
+        frame: &Frame,
+        handle: GizmoHandle,
+        ray: &ncollide3d::query::Ray<f32>,
+    ) -> Option<na::Point3<f32>> {
+        match (self.mode, handle) {
+            (GizmoMode::Rotate, GizmoHandle::Axis(ix)) | (_, GizmoHandle::Plane(ix)) => {
+                ray_plane(ray, &frame.center, &frame.axes[ix]).map(|toi| ray.point_at(toi))
+            }
+            (_, GizmoHandle::Axis(ix)) => closest_on_axis(ray, &frame.center, &frame.axes[ix])
+                .map(|along| frame.center + along * frame.axes[ix].into_inner()),
+        }
+    }
+
+    fn snap(&self, value: f32, step: f32) -> f32 {
+        if self.snap && step > 0.0 {
+            (value / step).round() * step
+        } else {
+            value
+        }
+    }
+
+    /// New position, rotation and scale of the dragged entity with the cursor ray at `ray`
+    fn apply(
+        &self,
+        drag: &Drag,
+        ray: &ncollide3d::query::Ray<f32>,
+    ) -> Option<(na::Point3<f32>, na::UnitQuaternion<f32>, na::Vector3<f32>)> {
+        let Drag {
+            frame,
+            handle,
+            grab,
+            parent_inverse,
+            position,
+            rotation,
+            scale,
+            ..
+        } = drag;
+        let current = self.grab_point(frame, *handle, ray)?;
+        let offset = current - *grab;
+        match (self.mode, *handle) {
+            (GizmoMode::Translate, handle) => {
+                let axes: Vec<usize> = match handle {
+                    GizmoHandle::Axis(ix) => vec![ix],
+                    GizmoHandle::Plane(ix) => {
+                        let (a, b) = other_axes(ix);
+                        vec![a, b]
+                    }
+                };
+                let delta = axes.into_iter().fold(na::Vector3::zeros(), |delta, ix| {
+                    let axis = frame.axes[ix].into_inner();
+                    delta + self.snap(offset.dot(&axis), self.grid) * axis
+                });
+                // the position is relative to the parent
+                let position = *position + parent_inverse.transform_vector(&delta);
+                Some((position, *rotation, *scale))
+            }
+            (GizmoMode::Rotate, GizmoHandle::Axis(ix)) => {
+                let axis = frame.axes[ix];
+                let (from, to) = (*grab - frame.center, current - frame.center);
+                let angle = from.cross(&to).dot(&axis).atan2(from.dot(&to));
+                let angle = self.snap(angle.to_degrees(), self.angle).to_radians();
+                let local_axis = na::Unit::try_new(parent_inverse.transform_vector(&axis), 1.0e-6)?;
+                let rotation = na::UnitQuaternion::from_axis_angle(&local_axis, angle) * *rotation;
+                Some((*position, rotation, *scale))
+            }
+            (GizmoMode::Scale, GizmoHandle::Axis(ix)) => {
+                let axis = frame.axes[ix].into_inner();
+                let grabbed = (*grab - frame.center).dot(&axis);
+                if grabbed.abs() < 1.0e-6 {
+                    return None;
+                }
+                let factor = (current - frame.center).dot(&axis) / grabbed;
+                let factor = self.snap(factor, self.scale_step).max(MIN_SCALE_FACTOR);
+                let mut scale = *scale;
+                scale[ix] *= factor;
+                Some((*position, *rotation, scale))
+            }
+            (_, GizmoHandle::Plane(_)) => None,
+        }
+    }
+
+    /// Geometry of the gizmo around the entity with `model_matrix`, the hovered or dragged
+    /// handle is highlighted
+    pub fn lines(
+        &self,
+        camera: &Camera,
+        model_matrix: &glm::Mat4,
+        rotation: &na::UnitQuaternion<f32>,
+    ) -> Vec<GizmoLine> {
+        let frame = self.frame(camera, model_matrix, rotation);
+        let active = self.drag.as_ref().map(|drag| drag.handle).or(self.hovered);
+        let color = |handle: GizmoHandle, ix: usize| {
+            if active == Some(handle) {
+                glm::vec4(1.0, 1.0, 0.0, 1.0)
+            } else {
+                let mut color = glm::vec4(0.0, 0.0, 0.0, 1.0);
+                color[ix] = 1.0;
+                color
+            }
+        };
+        let mut lines = vec![];
+        let mut line = |start: na::Point3<f32>, end: na::Point3<f32>, color: glm::Vec4| {
+            lines.push(GizmoLine { start, end, color });
+        };
+        for ix in 0..3 {
+            let axis = frame.axes[ix].into_inner();
+            let (a, b) = other_axes(ix);
+            let (a, b) = (frame.axes[a].into_inner(), frame.axes[b].into_inner());
+            let handle_color = color(GizmoHandle::Axis(ix), ix);
+            match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    let end = frame.center + frame.size * axis;
+                    line(frame.center, end, handle_color);
+                    // arrow heads for moving, boxes for scaling
+                    let tip = 0.08 * frame.size;
+                    let corners = match self.mode {
+                        GizmoMode::Translate => [
+                            end - 2.0 * tip * axis + tip * a,
+                            end - 2.0 * tip * axis + tip * b,
+                            end - 2.0 * tip * axis - tip * a,
+                            end - 2.0 * tip * axis - tip * b,
+                        ],
+                        _ => [
+                            end + tip * (a + b),
+                            end + tip * (b - a),
+                            end - tip * (a + b),
+                            end + tip * (a - b),
+                        ],
+                    };
+                    for (corner, next) in corners.iter().zip(corners.iter().cycle().skip(1)) {
+                        line(*corner, *next, handle_color);
+                        if self.mode == GizmoMode::Translate {
+                            line(*corner, end, handle_color);
+                        }
+                    }
+                }
+                GizmoMode::Rotate => {
+                    let point = |segment: usize| {
+                        let angle = segment as f32 / RING_SEGMENTS as f32 * 2.0 * f32::pi();
+                        frame.center + frame.size * (angle.cos() * a + angle.sin() * b)
+                    };
+                    for segment in 0..RING_SEGMENTS {
+                        line(point(segment), point(segment + 1), handle_color);
+                    }
+                }
+            }
+            if self.mode == GizmoMode::Translate {
+                let plane_color = color(GizmoHandle::Plane(ix), ix);
+                let (near, far) = (PLANE_HANDLE.0 * frame.size, PLANE_HANDLE.1 * frame.size);
+                let corners = [
+                    frame.center + near * a + near * b,
+                    frame.center + far * a + near * b,
+                    frame.center + far * a + far * b,
+                    frame.center + near * a + far * b,
+                ];
+                for (corner, next) in corners.iter().zip(corners.iter().cycle().skip(1)) {
+                    line(*corner, *next, plane_color);
+                }
+            }
+        }
+        lines
+    }
+
+    pub fn draw(&mut self, ui: &imgui::Ui) {
+        let mut changed = false;
+        ui.text(&im_str!("Gizmo:"));
+        for &(mode, label) in [
+            (GizmoMode::Translate, im_str!("Translate")),
+            (GizmoMode::Rotate, im_str!("Rotate")),
+            (GizmoMode::Scale, im_str!("Scale")),
+        ]
+        .iter()
+        {
+            if mode != GizmoMode::Translate {
+                ui.same_line(0.0);
+            }
+            if ui.radio_button_bool(label, self.mode == mode) {
+                self.mode = mode;
+                changed = true;
+            }
+        }
+        let mut local = self.space == GizmoSpace::Local;
+        if ui.checkbox(&im_str!("Local space"), &mut local) {
+            self.space = if local {
+                GizmoSpace::Local
+            } else {
+                GizmoSpace::World
+            };
+            changed = true;
+        }
+        changed |= ui.checkbox(&im_str!("Snap"), &mut self.snap);
+        changed |= ui.input_float(&im_str!("grid"), &mut self.grid).build();
+        changed |= ui.input_float(&im_str!("angle"), &mut self.angle).build();
+        changed |= ui
+            .input_float(&im_str!("scale step"), &mut self.scale_step)
+            .build();
+        self.grid = self.grid.max(0.001);
+        self.angle = self.angle.max(0.1);
+        self.scale_step = self.scale_step.max(0.001);
+        if changed {
+            // the grabbed handle may not exist in the new mode
+            self.drag = None;
+        }
+    }
+}
+
+/// Grabs gizmo handles under the cursor on `Action::Select` and drags the `Selected` entity
+/// with them until it is released. Runs before `MousePicking`, which ignores clicks that landed
+/// on a handle.
+pub struct ManipulateGizmo;
+
+impl ManipulateGizmo {
+    #[allow(clippy::too_many_arguments)]
+    pub fn exec(
+        input: &InputState,
+        actions: &ActionState,
+        runtime_config: &RuntimeConfiguration,
+        swapchain: &Swapchain,
+        camera: &Camera,
+        selected: Option<Entity>,
+        entities: &EntitiesStorage,
+        parents: &ComponentStorage<Parent>,
+        model_matrices: &ComponentStorage<glm::Mat4>,
+        positions: &mut ComponentStorage<na::Point3<f32>>,
+        rotations: &mut ComponentStorage<na::UnitQuaternion<f32>>,
+        scales: &mut ComponentStorage<Scale>,
+        gizmo: &mut Gizmo,
+    ) {
+        #[cfg(feature = "profiling")]
+        microprofile::scope!("ecs", "manipulate gizmo");
+        gizmo.hovered = None;
+        let entity = match selected {
+            Some(entity)
+                if !runtime_config.fly_mode
+                    && entities.is_alive(entity)
+                    && (model_matrices, &*positions, &*rotations, &*scales)
+                        .join_mask()
+                        .contains(entity.index) =>
+            {
+                entity
+            }
+            _ => {
+                gizmo.drag = None;
+                return;
+            }
+        };
+        if gizmo.drag.as_ref().map_or(false, |drag| {
+            drag.entity != entity || !actions.held(Action::Select)
+        }) {
+            gizmo.drag = None;
+        }
+        let ray = match input.cursor_position {
+            Some(cursor) => MousePicking::cursor_ray(
+                camera,
+                cursor,
+                (swapchain.width as f32, swapchain.height as f32),
+            ),
+            None => return,
+        };
+
+        if let Some(ref drag) = gizmo.drag {
+            if let Some((position, rotation, scale)) = gizmo.apply(drag, &ray) {
+                *positions.get_mut(entity).unwrap() = position;
+                *rotations.get_mut(entity).unwrap() = rotation;
+                *scales.get_mut(entity).unwrap() = Scale(scale);
+            }
+            return;
+        }
+
+        let frame = gizmo.frame(
+            camera,
+            model_matrices.get(entity).unwrap(),
+            rotations.get(entity).unwrap(),
+        );
+        gizmo.hovered = gizmo.hit_test(&frame, &ray);
+        if !actions.pressed(Action::Select) {
+            return;
+        }
+        let handle = match gizmo.hovered {
+            Some(handle) => handle,
+            None => return,
+        };
+        if let Some(grab) = gizmo.grab_point(&frame, handle, &ray) {
+            let parent_inverse = parent_matrix(entities, parents, model_matrices, entity.index)
+                .and_then(|(_, matrix)| matrix.try_inverse())
+                .unwrap_or_else(glm::Mat4::identity);
+            gizmo.drag = Some(Drag {
+                entity,
+                handle,
+                frame,
+                grab,
+                parent_inverse,
+                position: *positions.get(entity).unwrap(),
+                rotation: *rotations.get(entity).unwrap(),
+                scale: scales.get(entity).unwrap().0,
+            });
+        }
+    }
+}
+
+fn other_axes(ix: usize) -> (usize, usize) {
+    ((ix + 1) % 3, (ix + 2) % 3)
+}
+
+/// Time of impact of the ray with the plane, if it's ahead of the ray origin
+fn ray_plane(
+    ray: &ncollide3d::query::Ray<f32>,
+    point: &na::Point3<f32>,
+    normal: &na::Unit<na::Vector3<f32>>,
+) -> Option<f32> {
+    let denominator = normal.dot(&ray.dir);
+    if denominator.abs() < 1.0e-6 {
+        return None;
+    }
+    Some(normal.dot(&(point - ray.origin)) / denominator).filter(|toi| *toi >= 0.0)
+}
+
+/// Signed distance from `origin` along `axis` of the point on the axis line closest to the ray,
+/// none if they are parallel
+fn closest_on_axis(
+    ray: &ncollide3d::query::Ray<f32>,
+    origin: &na::Point3<f32>,
+    axis: &na::Unit<na::Vector3<f32>>,
+) -> Option<f32> {
+    let offset = origin - ray.origin;
+    let (b, c) = (axis.dot(&ray.dir), ray.dir.dot(&ray.dir));
+    let (d, e) = (axis.dot(&offset), ray.dir.dot(&offset));
+    let denominator = c - b * b;
+    if denominator.abs() < 1.0e-6 * c {
+        return None;
+    }
+    Some((b * e - c * d) / denominator)
+}
+
+/// Distance between the ray and the segment, and the time of impact on the ray where it's
+/// closest
+fn ray_segment_distance(
+    ray: &ncollide3d::query::Ray<f32>,
+    start: &na::Point3<f32>,
+    end: &na::Point3<f32>,
+) -> (f32, f32) {
+    let length = (end - start).norm();
+    let axis = na::Unit::new_normalize(end - start);
+    let along = closest_on_axis(ray, start, &axis)
+        .unwrap_or(0.0)
+        .max(0.0)
+        .min(length);
+    let point = start + along * axis.into_inner();
+    let toi = ((point - ray.origin).dot(&ray.dir) / ray.dir.norm_squared()).max(0.0);
+    ((ray.point_at(toi) - point).norm(), toi)
+}
+
+#[cfg(test)]
+fn test_drag(gizmo: &Gizmo, handle: GizmoHandle, grab: &ncollide3d::query::Ray<f32>) -> Drag {
+    let frame = Frame {
+        center: na::Point3::new(0.0, 0.0, 10.0),
+        axes: [
+            na::Vector3::x_axis(),
+            na::Vector3::y_axis(),
+            na::Vector3::z_axis(),
+        ],
+        size: 1.0,
+    };
+    Drag {
+        entity: EntitiesStorage::new().allocate(),
+        handle,
+        frame,
+        grab: gizmo.grab_point(&frame, handle, grab).unwrap(),
+        parent_inverse: glm::Mat4::identity(),
+        position: frame.center,
+        rotation: na::UnitQuaternion::identity(),
+        scale: na::Vector3::repeat(1.0),
+    }
+}
+
+#[cfg(test)]
+fn cursor(x: f32, y: f32) -> ncollide3d::query::Ray<f32> {
+    ncollide3d::query::Ray::new(na::Point3::new(x, y, 0.0), na::Vector3::z())
+}
+
+#[test]
+fn test_gizmo_hit_test() {
+    let gizmo = Gizmo::new();
+    let drag = test_drag(&gizmo, GizmoHandle::Axis(0), &cursor(0.5, 0.0));
+    let frame = drag.frame;
+    let from_above =
+        |x: f32, z: f32| ncollide3d::query::Ray::new(na::Point3::new(x, 5.0, z), -na::Vector3::y());
+    assert_eq!(
+        gizmo.hit_test(&frame, &from_above(0.5, 10.0)),
+        Some(GizmoHandle::Axis(0))
+    );
+    assert_eq!(
+        gizmo.hit_test(&frame, &from_above(0.3, 10.3)),
+        Some(GizmoHandle::Plane(1))
+    );
+    assert_eq!(gizmo.hit_test(&frame, &from_above(1.5, 10.0)), None);
+    let rotate = Gizmo {
+        mode: GizmoMode::Rotate,
+        ..Gizmo::new()
+    };
+    assert_eq!(
+        rotate.hit_test(&frame, &from_above(0.0, 11.0)),
+        Some(GizmoHandle::Axis(1))
+    );
+    assert_eq!(rotate.hit_test(&frame, &from_above(0.0, 10.5)), None);
+}
+
+#[test]
+fn test_gizmo_drag() {
+    let mut gizmo = Gizmo::new();
+    // along x, with the grabbed point following the cursor
+    let drag = test_drag(&gizmo, GizmoHandle::Axis(0), &cursor(0.5, 0.0));
+    let (position, _, _) = gizmo.apply(&drag, &cursor(1.2, 3.0)).unwrap();
+    assert!((position - na::Point3::new(0.7, 0.0, 10.0)).norm() < 1e-5);
+    gizmo.snap = true;
+    let (position, _, _) = gizmo.apply(&drag, &cursor(1.2, 3.0)).unwrap();
+    assert!((position - na::Point3::new(0.5, 0.0, 10.0)).norm() < 1e-5);
+    // in a parent scaled by 2, moving 1 in the world moves 0.5 locally
+    let parent_inverse = glm::scaling(&glm::vec3(0.5, 0.5, 0.5));
+    let scaled = Drag {
+        parent_inverse,
+        position: na::Point3::new(0.0, 0.0, 5.0),
+        ..test_drag(&gizmo, GizmoHandle::Axis(0), &cursor(0.5, 0.0))
+    };
+    let (position, _, _) = gizmo.apply(&scaled, &cursor(1.5, 0.0)).unwrap();
+    assert!((position - na::Point3::new(0.5, 0.0, 5.0)).norm() < 1e-5);
+
+    // a quarter turn around z, snapped from a bit less
+    gizmo.mode = GizmoMode::Rotate;
+    let drag = test_drag(&gizmo, GizmoHandle::Axis(2), &cursor(1.0, 0.0));
+    let (_, rotation, _) = gizmo.apply(&drag, &cursor(0.1, 1.0)).unwrap();
+    let expected = na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), f32::frac_pi_2());
+    assert!(rotation.angle_to(&expected) < 1e-5);
+
+    gizmo.mode = GizmoMode::Scale;
+    gizmo.snap = false;
+    let drag = test_drag(&gizmo, GizmoHandle::Axis(1), &cursor(0.0, 0.5));
+    let (_, _, scale) = gizmo.apply(&drag, &cursor(0.0, 1.5)).unwrap();
+    assert!((scale - na::Vector3::new(1.0, 3.0, 1.0)).norm() < 1e-5);
+    let (_, _, scale) = gizmo.apply(&drag, &cursor(0.0, -1.0)).unwrap();
+    assert!((scale.y - MIN_SCALE_FACTOR).abs() < 1e-5);
+}
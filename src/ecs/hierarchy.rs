@@ -0,0 +1,173 @@
+use super::{components::Parent, custom::*};
+use hashbrown::HashMap;
+
+/// `(child, parent)` links between entities in `mask`, sorted so that every parent comes before
+/// its children. Panics if the `Parent` components form a cycle.
+pub fn hierarchy_order(
+    entities: &EntitiesStorage,
+    parents: &ComponentStorage<Parent>,
+    mask: &croaring::Bitmap,
+) -> Vec<(u32, u32)> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    let mut linked = croaring::Bitmap::create();
+    for (entity_id, parent) in join((mask, parents)) {
+        if entities.is_alive(parent.0) && mask.contains(parent.0.index) {
+            children
+                .entry(parent.0.index)
+                .or_insert_with(Vec::new)
+                .push(entity_id);
+            linked.add(entity_id);
+        }
+    }
+
+    let mut order = Vec::with_capacity(linked.cardinality() as usize);
+    let mut frontier = (mask - &linked).to_vec();
+    while let Some(parent) = frontier.pop() {
+        if let Some(children) = children.get(&parent) {
+            for &child in children {
+                order.push((child, parent));
+                frontier.push(child);
+            }
+        }
+    }
+    assert_eq!(
+        order.len() as u64,
+        linked.cardinality(),
+        "Parent components form a cycle"
+    );
+
+    order
+}
+
+/// Turns the local matrices of `changed` entities into world matrices, following `order` from
+/// `hierarchy_order()`. Children of changed parents are added to `changed` and must already hold
+/// their local matrix as well.
+pub fn propagate_world_matrices(
+    order: &[(u32, u32)],
+    changed: &croaring::Bitmap,
+    model_matrices: &mut ComponentStorage<glm::Mat4>,
+) {
+    for &(child, parent) in order {
+        if changed.contains(child) {
+            let parent_matrix = *model_matrices.get(parent).unwrap();
+            let model_matrix = model_matrices.get_mut(child).unwrap();
+            *model_matrix = parent_matrix * *model_matrix;
+        }
+    }
+}
+
+/// Model matrix of the parent the entity is attached to, if it has one
+pub fn parent_matrix<'a>(
+    entities: &EntitiesStorage,
+    parents: &ComponentStorage<Parent>,
+    model_matrices: &'a ComponentStorage<glm::Mat4>,
+    entity_id: u32,
+) -> Option<(u32, &'a glm::Mat4)> {
+    if !parents.mask().contains(entity_id) {
+        return None;
+    }
+    let parent = parents.get(entity_id).unwrap().0;
+    if entities.is_alive(parent) && model_matrices.mask().contains(parent.index) {
+        model_matrices
+            .get(parent.index)
+            .map(|matrix| (parent.index, matrix))
+    } else {
+        None
+    }
+}
+
+/// Children need their world matrix recalculated whenever an ancestor changes
+pub fn propagate_changes(order: &[(u32, u32)], changed: &mut croaring::Bitmap) {
+    for &(child, parent) in order {
+        if changed.contains(parent) {
+            changed.add(child);
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_storages(
+    parent_links: &[(usize, usize)],
+) -> (EntitiesStorage, ComponentStorage<Parent>, Vec<Entity>) {
+    let mut entities = EntitiesStorage::new();
+    let mut parents = ComponentStorage::new();
+    let ixes = entities.allocate_many(5);
+    for &(child, parent) in parent_links {
+        parents.insert(ixes[child], Parent(ixes[parent]));
+    }
+    (entities, parents, ixes)
+}
+
+#[test]
+fn test_order() {
+    let (entities, parents, _) = test_storages(&[(0, 3), (3, 4), (1, 0), (2, 4)]);
+    let order = hierarchy_order(&entities, &parents, entities.mask());
+    assert_eq!(order.len(), 4);
+    let position = |ix| order.iter().position(|&(child, _)| child == ix);
+    assert!(position(3) < position(0));
+    assert!(position(0) < position(1));
+    assert!(order.contains(&(2, 4)));
+
+    // only links within the mask count
+    let mut mask = entities.mask().clone();
+    mask.remove(3);
+    let order = hierarchy_order(&entities, &parents, &mask);
+    assert_eq!(order.len(), 2);
+    assert!(order.contains(&(1, 0)) && order.contains(&(2, 4)));
+}
+
+#[test]
+fn test_dead_parent() {
+    let (mut entities, mut parents, ixes) = test_storages(&[(1, 0)]);
+    entities.remove(ixes[0]);
+    entities.maintain();
+    let reused = entities.allocate();
+    assert_eq!(reused.index, 0);
+    parents.insert(ixes[2], Parent(reused));
+    // entity 1 still points to the previous occupant of slot 0
+    let order = hierarchy_order(&entities, &parents, entities.mask());
+    assert_eq!(order, vec![(2, 0)]);
+}
+
+#[test]
+#[should_panic(expected = "cycle")]
+fn test_cycle() {
+    let (entities, parents, _) = test_storages(&[(0, 1), (1, 2), (2, 0)]);
+    hierarchy_order(&entities, &parents, entities.mask());
+}
+
+#[test]
+fn test_propagation() {
+    let (entities, parents, ixes) = test_storages(&[(1, 0), (2, 1)]);
+    let mut model_matrices = ComponentStorage::new();
+    for entity in ixes.iter() {
+        model_matrices.insert(*entity, glm::translation(&glm::vec3(1.0, 0.0, 0.0)));
+    }
+    let order = hierarchy_order(&entities, &parents, entities.mask());
+    let mut changed = croaring::Bitmap::of(&[0]);
+    propagate_changes(&order, &mut changed);
+    assert_eq!(changed.to_vec(), vec![0, 1, 2]);
+
+    propagate_world_matrices(&order, &changed, &mut model_matrices);
+    let world_x = |ix: u32| model_matrices.get(ix).unwrap()[(0, 3)];
+    assert_eq!(world_x(0), 1.0);
+    assert_eq!(world_x(1), 2.0);
+    assert_eq!(world_x(2), 3.0);
+    assert_eq!(world_x(3), 1.0);
+}
+
+#[test]
+fn test_parent_matrix() {
+    let (entities, parents, ixes) = test_storages(&[(1, 0), (2, 3)]);
+    let mut model_matrices = ComponentStorage::new();
+    model_matrices.insert(ixes[0], glm::translation(&glm::vec3(0.0, 2.0, 0.0)));
+    let (parent, matrix) = parent_matrix(&entities, &parents, &model_matrices, 1).unwrap();
+    assert_eq!(parent, 0);
+    assert_eq!(
+        matrix.transform_point(&na::Point3::new(1.0, 0.0, 0.0)),
+        na::Point3::new(1.0, 2.0, 0.0)
+    );
+    // no model matrix on the parent
+    assert!(parent_matrix(&entities, &parents, &model_matrices, 2).is_none());
+    assert!(parent_matrix(&entities, &parents, &model_matrices, 0).is_none());
+}
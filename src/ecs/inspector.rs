@@ -0,0 +1,100 @@
+use super::{custom::*, systems::Selected, world::World};
+use imgui::im_str;
+
+type ComponentEditor = Box<dyn Fn(&imgui::Ui, &World, Entity)>;
+
+/// Entity browser for the debug GUI. Lists every live entity and shows the components of the
+/// `Selected` one, components of types registered here are editable as well.
+pub struct Inspector {
+    editors: Vec<ComponentEditor>,
+}
+
+impl Inspector {
+    pub fn new() -> Inspector {
+        Inspector { editors: vec![] }
+    }
+
+    /// Makes components of type `T` editable, `edit` draws the widgets and returns whether the
+    /// value was changed. Changes are applied through the `EntityCommands` queue.
+    pub fn register<T, F>(&mut self, edit: F)
+    where
+        T: Clone + Send + Sync + 'static,
+        F: Fn(&imgui::Ui, &mut T) -> bool + 'static,
+    {
+        self.editors.push(Box::new(move |ui, world, entity| {
+            let value = {
+                let storage = world.storage::<T>();
+                if !storage.mask().contains(entity.index) {
+                    return;
+                }
+                storage.get(entity).cloned()
+            };
+            if let Some(mut value) = value {
+                if edit(ui, &mut value) {
+                    world
+                        .commands()
+                        .modify::<T, _>(entity, move |component| *component = value);
+                }
+            }
+        }));
+    }
+
+    pub fn draw(&mut self, ui: &imgui::Ui, world: &World) {
+        let mut selected = *world.read::<Selected>();
+        if selected
+            .entity
+            .map_or(false, |entity| !world.entities().is_alive(entity))
+        {
+            selected = Selected::default();
+        }
+
+        if ui.collapsing_header(&im_str!("Entities")).build() {
+            let alive = world.entities().mask().clone();
+            for (ix, entity_id) in alive.iter().enumerate() {
+                if ix % 10 != 0 {
+                    ui.same_line(0.0);
+                }
+                let is_selected = selected.entity.map_or(false, |e| e.index == entity_id);
+                if ui.radio_button_bool(&im_str!("{}", entity_id), is_selected) {
+                    selected = Selected {
+                        entity: Some(world.entities().entity(entity_id)),
+                        point: None,
+                    };
+                }
+            }
+            ui.spacing();
+        }
+
+        if let Some(entity) = selected.entity {
+            if ui
+                .collapsing_header(&im_str!("Selected entity"))
+                .default_open(true)
+                .build()
+            {
+                ui.text(&im_str!(
+                    "Entity {} generation {}:",
+                    entity.index,
+                    entity.generation
+                ));
+                if let Some(point) = selected.point {
+                    ui.text(&im_str!(
+                        "Picked at ({:.2}, {:.2}, {:.2})",
+                        point.x,
+                        point.y,
+                        point.z
+                    ));
+                }
+                for name in world.component_names(entity.index) {
+                    ui.bullet_text(&im_str!("{}", name));
+                }
+                ui.spacing();
+                for editor in self.editors.iter() {
+                    editor(ui, world, entity);
+                }
+                ui.spacing();
+            }
+        }
+
+        *world.write::<Selected>() = selected;
+    }
+}
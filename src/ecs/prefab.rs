@@ -0,0 +1,281 @@
+use super::{commands::EntityCommands, components::*, custom::*, scene::SceneLoader, world::World};
+use crate::renderer::{
+    GltfMesh, GltfMeshBaseColorTexture, GraphicsCommandPool, Image, RenderFrame,
+};
+use hashbrown::HashMap;
+use std::sync::Arc;
+
+/// Bundle of component values to spawn entities from. Unset fields are not inserted, so the
+/// same type describes both the prefab and the overrides it is spawned with.
+#[derive(Clone, Default)]
+pub struct Prefab {
+    pub position: Option<na::Point3<f32>>,
+    /// Defaults to identity for entities with a position
+    pub rotation: Option<na::UnitQuaternion<f32>>,
+    /// Defaults to 1.0 for entities with a mesh
+    pub scale: Option<na::Vector3<f32>>,
+    pub parent: Option<Entity>,
+    pub light: Option<Light>,
+    pub projectile_target: Option<na::Point3<f32>>,
+    pub projectile_velocity: Option<f32>,
+    /// Path to a glTF file, only its first mesh is used
+    pub mesh: Option<String>,
+    /// Path to a glTF file to take the base color texture from, defaults to `mesh`
+    pub texture: Option<String>,
+}
+
+impl Prefab {
+    /// Fields set in `overrides` replace the ones in the prefab
+    pub fn with_overrides(&self, overrides: Prefab) -> Prefab {
+        Prefab {
+            position: overrides.position.or(self.position),
+            rotation: overrides.rotation.or(self.rotation),
+            scale: overrides.scale.or(self.scale),
+            parent: overrides.parent.or(self.parent),
+            light: overrides.light.or_else(|| self.light.clone()),
+            projectile_target: overrides.projectile_target.or(self.projectile_target),
+            projectile_velocity: overrides.projectile_velocity.or(self.projectile_velocity),
+            mesh: overrides.mesh.or_else(|| self.mesh.clone()),
+            texture: overrides.texture.or_else(|| self.texture.clone()),
+        }
+    }
+}
+
+/// A `Prefab` with its asset references resolved, ready to be inserted without the renderer
+pub struct PrefabInstance {
+    prefab: Prefab,
+    assets: Option<(GltfMesh, Arc<Image>)>,
+}
+
+impl PrefabInstance {
+    /// `load` returns the mesh and base color texture of a glTF file
+    pub fn new<F: FnMut(&str) -> (GltfMesh, Arc<Image>)>(prefab: Prefab, mut load: F) -> Self {
+        let assets = match prefab.mesh {
+            Some(ref mesh_path) => {
+                let (mesh, base_color) = load(mesh_path);
+                let base_color = match prefab.texture {
+                    Some(ref texture_path) => load(texture_path).1,
+                    None => base_color,
+                };
+                Some((mesh, base_color))
+            }
+            None => {
+                assert!(prefab.texture.is_none(), "prefab has a texture but no mesh");
+                None
+            }
+        };
+        PrefabInstance { prefab, assets }
+    }
+
+    pub fn insert(&self, world: &World, entity: Entity) {
+        let prefab = &self.prefab;
+        if let Some(position) = prefab.position {
+            world.storage_mut().insert(entity, position);
+        }
+        if prefab.rotation.is_some() || prefab.position.is_some() {
+            let rotation = prefab.rotation.unwrap_or_else(na::UnitQuaternion::identity);
+            world.storage_mut().insert(entity, rotation);
+        }
+        if prefab.scale.is_some() || self.assets.is_some() {
+            let scale = prefab.scale.unwrap_or_else(|| na::Vector3::repeat(1.0));
+            world.storage_mut().insert(entity, Scale(scale));
+        }
+        if let Some(parent) = prefab.parent {
+            world.storage_mut().insert(entity, Parent(parent));
+        }
+        if let Some(ref light) = prefab.light {
+            world.storage_mut().insert(entity, light.clone());
+        }
+        if let Some(target) = prefab.projectile_target {
+            world.storage_mut().insert(entity, ProjectileTarget(target));
+        }
+        if let Some(velocity) = prefab.projectile_velocity {
+            world
+                .storage_mut()
+                .insert(entity, ProjectileVelocity(velocity));
+        }
+        if let Some((ref mesh, ref base_color)) = self.assets {
+            world.storage_mut().insert(entity, mesh.clone());
+            world
+                .storage_mut()
+                .insert(entity, GltfMeshBaseColorTexture(Arc::clone(base_color)));
+        }
+    }
+
+    pub fn spawn(&self, world: &World) -> Entity {
+        let entity = world.entities_mut().allocate();
+        self.insert(world, entity);
+        entity
+    }
+}
+
+/// Named prefabs. Their assets are loaded on registration, so spawning only needs the `World`.
+pub struct Prefabs {
+    registered: HashMap<String, Prefab>,
+    assets: HashMap<String, (GltfMesh, Arc<Image>)>,
+}
+
+impl Prefabs {
+    pub fn new() -> Prefabs {
+        Prefabs {
+            registered: HashMap::new(),
+            assets: HashMap::new(),
+        }
+    }
+
+    pub fn register(
+        &mut self,
+        renderer: &RenderFrame,
+        graphics_command_pool: &GraphicsCommandPool,
+        scene_loader: &mut SceneLoader,
+        name: &str,
+        prefab: Prefab,
+    ) {
+        for path in prefab.mesh.iter().chain(prefab.texture.iter()) {
+            let loaded = scene_loader.mesh(renderer, graphics_command_pool, path);
+            self.assets.insert(path.clone(), loaded);
+        }
+        self.registered.insert(name.to_string(), prefab);
+    }
+
+    /// Asset references in `overrides` must be used by one of the registered prefabs
+    pub fn instantiate(&self, name: &str, overrides: Prefab) -> PrefabInstance {
+        let prefab = self
+            .registered
+            .get(name)
+            .unwrap_or_else(|| panic!("prefab {} is not registered", name))
+            .with_overrides(overrides);
+        PrefabInstance::new(prefab, |path| {
+            let (mesh, base_color) = self
+                .assets
+                .get(path)
+                .unwrap_or_else(|| panic!("asset {} is not used by any registered prefab", path));
+            (mesh.clone(), Arc::clone(base_color))
+        })
+    }
+
+    pub fn spawn(&self, world: &World, name: &str, overrides: Prefab) -> Entity {
+        self.instantiate(name, overrides).spawn(world)
+    }
+
+    /// Spawns through the command queue, for systems that only have shared borrows
+    pub fn spawn_deferred(&self, commands: &EntityCommands, name: &str, overrides: Prefab) {
+        let instance = self.instantiate(name, overrides);
+        commands.spawn(move |world, entity| instance.insert(world, entity));
+    }
+}
+
+/// `World` with every storage a `Prefab` or `Snapshot` touches registered
+#[cfg(test)]
+pub fn test_world() -> World {
+    let mut world = World::new();
+    world.register::<na::Point3<f32>>();
+    world.register::<na::UnitQuaternion<f32>>();
+    world.register::<Scale>();
+    world.register::<Parent>();
+    world.register::<Light>();
+    world.register::<ProjectileTarget>();
+    world.register::<ProjectileVelocity>();
+    world.register::<GltfMesh>();
+    world.register::<GltfMeshBaseColorTexture>();
+    world
+}
+
+#[cfg(test)]
+fn test_prefabs() -> Prefabs {
+    let mut prefabs = Prefabs::new();
+    prefabs.registered.insert(
+        "projectile".to_string(),
+        Prefab {
+            scale: Some(na::Vector3::repeat(0.5)),
+            projectile_velocity: Some(20.0),
+            ..Prefab::default()
+        },
+    );
+    prefabs
+}
+
+#[test]
+fn test_overrides() {
+    let prefab = Prefab {
+        position: Some(na::Point3::new(1.0, 0.0, 0.0)),
+        light: Some(Light { strength: 0.5 }),
+        ..Prefab::default()
+    };
+    let overridden = prefab.with_overrides(Prefab {
+        position: Some(na::Point3::new(2.0, 0.0, 0.0)),
+        projectile_velocity: Some(3.0),
+        ..Prefab::default()
+    });
+    assert_eq!(overridden.position, Some(na::Point3::new(2.0, 0.0, 0.0)));
+    assert_eq!(overridden.light, Some(Light { strength: 0.5 }));
+    assert_eq!(overridden.projectile_velocity, Some(3.0));
+    assert!(overridden.rotation.is_none());
+}
+
+#[test]
+fn test_spawn() {
+    let world = test_world();
+    let prefabs = test_prefabs();
+    let projectile = prefabs.spawn(
+        &world,
+        "projectile",
+        Prefab {
+            position: Some(na::Point3::new(0.0, 1.0, 0.0)),
+            projectile_target: Some(na::Point3::new(0.0, 1.0, 100.0)),
+            ..Prefab::default()
+        },
+    );
+    assert_eq!(
+        *world.storage::<na::Point3<f32>>().get(projectile).unwrap(),
+        na::Point3::new(0.0, 1.0, 0.0)
+    );
+    assert_eq!(
+        *world
+            .storage::<na::UnitQuaternion<f32>>()
+            .get(projectile)
+            .unwrap(),
+        na::UnitQuaternion::identity()
+    );
+    assert_eq!(
+        *world.storage::<Scale>().get(projectile).unwrap(),
+        Scale(na::Vector3::repeat(0.5))
+    );
+    assert_eq!(
+        world
+            .storage::<ProjectileVelocity>()
+            .get(projectile)
+            .unwrap()
+            .0,
+        20.0
+    );
+    assert!(world.storage::<Light>().mask().is_empty());
+    assert!(world.storage::<GltfMesh>().mask().is_empty());
+}
+
+#[test]
+fn test_spawn_deferred() {
+    let world = test_world();
+    let prefabs = test_prefabs();
+    prefabs.spawn_deferred(world.commands(), "projectile", Prefab::default());
+    prefabs.spawn_deferred(world.commands(), "projectile", Prefab::default());
+    assert!(world.entities().mask().is_empty());
+    world.maintain();
+    assert_eq!(world.entities().mask().to_vec(), vec![0, 1]);
+    assert_eq!(
+        world.storage::<ProjectileVelocity>().inserted().to_vec(),
+        vec![0, 1]
+    );
+}
+
+#[test]
+#[should_panic(expected = "is not used by any registered prefab")]
+fn test_unknown_asset() {
+    test_prefabs().instantiate(
+        "projectile",
+        Prefab {
+            mesh: Some("unknown.gltf".to_string()),
+            ..Prefab::default()
+        },
+    );
+}
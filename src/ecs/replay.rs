@@ -0,0 +1,186 @@
+use super::systems::{FrameTiming, InputState};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct RecordedFrame {
+    time_delta: f32,
+    input: InputState,
+    /// Swapchain extent the cursor position is relative to, in physical pixels
+    window_size: (u32, u32),
+}
+
+/// Input of every frame in a session along with the frame durations, which decide how many
+/// simulation steps run, and the window size. Replaying it against the same scene reproduces
+/// the simulation. Cursor positions are rescaled to the window size during the replay, so
+/// picking and the gizmo only behave the same when the aspect ratio matches.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct InputRecording {
+    frames: Vec<RecordedFrame>,
+}
+
+impl InputRecording {
+    pub fn record(
+        &mut self,
+        input: &InputState,
+        frame_timing: &FrameTiming,
+        window_size: (u32, u32),
+    ) {
+        self.frames.push(RecordedFrame {
+            time_delta: frame_timing.time_delta(),
+            input: input.clone(),
+            window_size,
+        });
+    }
+
+    pub fn save(&self, path: &str) {
+        let file = File::create(path)
+            .unwrap_or_else(|err| panic!("failed to create input recording {}: {}", path, err));
+        serde_json::to_writer(BufWriter::new(file), self)
+            .unwrap_or_else(|err| panic!("failed to write input recording {}: {}", path, err));
+    }
+
+    pub fn open(path: &str) -> InputRecording {
+        let file = File::open(path)
+            .unwrap_or_else(|err| panic!("failed to open input recording {}: {}", path, err));
+        serde_json::from_reader(BufReader::new(file))
+            .unwrap_or_else(|err| panic!("failed to parse input recording {}: {}", path, err))
+    }
+}
+
+/// Feeds an `InputRecording` back frame by frame, in place of the window events and the clock
+pub struct InputReplay {
+    recording: InputRecording,
+    next_frame: usize,
+}
+
+impl InputReplay {
+    pub fn new(recording: InputRecording) -> InputReplay {
+        InputReplay {
+            recording,
+            next_frame: 0,
+        }
+    }
+
+    /// Returns false once every recorded frame was replayed, from then on there is no input and
+    /// no time passes. `window_size` is the current one, cursor positions are scaled to it.
+    pub fn exec(
+        &mut self,
+        input: &mut InputState,
+        frame_timing: &mut FrameTiming,
+        window_size: (u32, u32),
+    ) -> bool {
+        match self.recording.frames.get(self.next_frame) {
+            Some(frame) => {
+                input.clone_from(&frame.input);
+                input.cursor_position = frame.input.cursor_position.map(|(x, y)| {
+                    let (width, height) = frame.window_size;
+                    (
+                        x * window_size.0 as f32 / width as f32,
+                        y * window_size.1 as f32 / height as f32,
+                    )
+                });
+                frame_timing.advance(frame.time_delta);
+                self.next_frame += 1;
+                true
+            }
+            None => {
+                input.clear();
+                frame_timing.advance(0.0);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+const WINDOW_SIZE: (u32, u32) = (800, 600);
+
+#[cfg(test)]
+fn fly_through(recording: InputRecording) -> na::Point3<f32> {
+    use super::{
+        bindings::{ActionState, KeyBindings, MapActions},
+        custom::{ComponentStorage, EntitiesStorage},
+        systems::{Camera, FlyCamera, RuntimeConfiguration, SelectCameraController},
+    };
+
+    let mut replay = InputReplay::new(recording);
+    let key_bindings = KeyBindings::default();
+    let mut actions = ActionState::default();
+    let mut input = InputState::default();
+    let mut frame_timing = FrameTiming::default();
+    let mut runtime_config = RuntimeConfiguration::new();
+    let mut camera = Camera::default();
+    let mut fly_camera = FlyCamera::default();
+    let entities = EntitiesStorage::new();
+    let positions = ComponentStorage::new();
+    while replay.exec(&mut input, &mut frame_timing, WINDOW_SIZE) {
+        MapActions::exec(&input, &key_bindings, &mut actions);
+        SelectCameraController::exec(
+            &actions,
+            None,
+            &entities,
+            &positions,
+            &camera,
+            &mut runtime_config,
+        );
+        fly_camera.exec(
+            &input,
+            &actions,
+            &frame_timing,
+            &runtime_config,
+            &mut camera,
+        );
+    }
+    camera.position
+}
+
+#[test]
+fn test_replay() {
+    use super::systems::FIXED_TIME_STEP;
+    use winit::event::VirtualKeyCode;
+
+    let mut recording = InputRecording::default();
+    let mut frame_timing = FrameTiming::default();
+    frame_timing.advance(FIXED_TIME_STEP);
+    // enter fly mode, turn right by 90 degrees and fly forward for three steps
+    let mut input = InputState {
+        key_presses: vec![Some(VirtualKeyCode::G), Some(VirtualKeyCode::W)],
+        mouse_delta: (90.0, 0.0),
+        ..InputState::default()
+    };
+    recording.record(&input, &frame_timing, WINDOW_SIZE);
+    input.clear();
+    recording.record(&input, &frame_timing, WINDOW_SIZE);
+    recording.record(&input, &frame_timing, WINDOW_SIZE);
+    input.key_releases = vec![Some(VirtualKeyCode::W)];
+    recording.record(&input, &frame_timing, WINDOW_SIZE);
+    assert_eq!(recording.frames.len(), 4);
+
+    let serialized = serde_json::to_string(&recording).unwrap();
+    let loaded: InputRecording = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(loaded, recording);
+
+    let position = fly_through(loaded);
+    assert!((position - na::Point3::new(0.05, 1.0, 2.0)).norm() < 1e-5);
+    assert_eq!(fly_through(recording), position);
+}
+
+#[test]
+fn test_replay_cursor_scaling() {
+    let mut recording = InputRecording::default();
+    let frame_timing = FrameTiming::default();
+    let input = InputState {
+        cursor_position: Some((400.0, 150.0)),
+        ..InputState::default()
+    };
+    recording.record(&input, &frame_timing, WINDOW_SIZE);
+    let mut replay = InputReplay::new(recording);
+    let mut replayed = InputState::default();
+    let mut frame_timing = FrameTiming::default();
+    assert!(replay.exec(&mut replayed, &mut frame_timing, (1600, 1200)));
+    assert_eq!(replayed.cursor_position, Some((800.0, 300.0)));
+}
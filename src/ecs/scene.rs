@@ -0,0 +1,266 @@
+use super::{
+    components::*,
+    custom::*,
+    prefab::{Prefab, PrefabInstance},
+    world::World,
+};
+use crate::renderer::{
+    load_gltf, up_vector, GltfMesh, GraphicsCommandPool, Image, LoadedMesh, RenderFrame,
+};
+use hashbrown::HashMap;
+use serde_derive::Deserialize;
+use std::{fs::File, io::BufReader, sync::Arc};
+
+/// Declarative description of the entities to spawn, read from a JSON file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scene {
+    pub entities: Vec<SceneEntity>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneEntity {
+    /// Index of another entity in the scene, position, rotation and scale are relative to it.
+    /// This is how a glTF node hierarchy is expressed.
+    pub parent: Option<usize>,
+    pub position: [f32; 3],
+    #[serde(default)]
+    pub rotation: SceneRotation,
+    /// Defaults to 1.0 for entities with a mesh
+    pub scale: Option<SceneScale>,
+    pub light: Option<Light>,
+    /// Path to a glTF file, only its first mesh is used
+    pub mesh: Option<String>,
+    /// Path to a glTF file to take the base color texture from, defaults to `mesh`
+    pub texture: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SceneRotation {
+    Identity,
+    LookAt([f32; 3]),
+    AxisAngle { axis: [f32; 3], degrees: f32 },
+}
+
+/// Either a single factor or one per axis
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum SceneScale {
+    Uniform(f32),
+    PerAxis([f32; 3]),
+}
+
+impl SceneScale {
+    pub fn to_vector(&self) -> na::Vector3<f32> {
+        match *self {
+            SceneScale::Uniform(scale) => na::Vector3::repeat(scale),
+            SceneScale::PerAxis(scale) => na::Vector3::from(scale),
+        }
+    }
+}
+
+impl Default for SceneRotation {
+    fn default() -> SceneRotation {
+        SceneRotation::Identity
+    }
+}
+
+impl SceneRotation {
+    pub fn to_quaternion(&self, position: &na::Point3<f32>) -> na::UnitQuaternion<f32> {
+        match *self {
+            SceneRotation::Identity => na::UnitQuaternion::identity(),
+            SceneRotation::LookAt(target) => {
+                na::UnitQuaternion::look_at_lh(&(na::Point3::from(target) - position), &up_vector())
+            }
+            SceneRotation::AxisAngle { axis, degrees } => na::UnitQuaternion::from_axis_angle(
+                &na::Unit::new_normalize(na::Vector3::from(axis)),
+                degrees.to_radians(),
+            ),
+        }
+    }
+}
+
+impl Scene {
+    pub fn parse(source: &str) -> Scene {
+        serde_json::from_str(source).unwrap_or_else(|err| panic!("failed to parse scene: {}", err))
+    }
+
+    pub fn open(path: &str) -> Scene {
+        let file =
+            File::open(path).unwrap_or_else(|err| panic!("failed to open scene {}: {}", path, err));
+        serde_json::from_reader(BufReader::new(file))
+            .unwrap_or_else(|err| panic!("failed to parse scene {}: {}", path, err))
+    }
+}
+
+/// Spawns scenes into the `World`. Every glTF file is loaded once and shared by all the
+/// entities that reference it.
+pub struct SceneLoader {
+    loaded: HashMap<String, (GltfMesh, Arc<Image>)>,
+}
+
+impl SceneLoader {
+    pub fn new() -> SceneLoader {
+        SceneLoader {
+            loaded: HashMap::new(),
+        }
+    }
+
+    /// Mesh and base color texture of the glTF file, loaded on first use
+    pub fn mesh(
+        &mut self,
+        renderer: &RenderFrame,
+        graphics_command_pool: &GraphicsCommandPool,
+        path: &str,
+    ) -> (GltfMesh, Arc<Image>) {
+        let (mesh, base_color) = self.loaded.entry(path.to_string()).or_insert_with(|| {
+            let LoadedMesh {
+                vertex_buffer,
+                normal_buffer,
+                uv_buffer,
+                index_buffers,
+                vertex_len,
+                aabb,
+                collision_mesh,
+                base_color,
+            } = load_gltf(renderer, graphics_command_pool, path);
+            let mesh = GltfMesh {
+                vertex_buffer: Arc::new(vertex_buffer),
+                normal_buffer: Arc::new(normal_buffer),
+                uv_buffer: Arc::new(uv_buffer),
+                index_buffers: Arc::new(index_buffers),
+                vertex_len,
+                aabb,
+                collision_mesh: Arc::new(collision_mesh),
+            };
+            (mesh, Arc::new(base_color))
+        });
+        (mesh.clone(), Arc::clone(base_color))
+    }
+
+    /// Path of the glTF file the mesh came from
+    pub fn mesh_path(&self, mesh: &GltfMesh) -> Option<&str> {
+        self.loaded
+            .iter()
+            .find(|(_, (loaded, _))| Arc::ptr_eq(&loaded.vertex_buffer, &mesh.vertex_buffer))
+            .map(|(path, _)| path.as_str())
+    }
+
+    /// Path of the glTF file the base color texture came from
+    pub fn texture_path(&self, texture: &Arc<Image>) -> Option<&str> {
+        self.loaded
+            .iter()
+            .find(|(_, (_, base_color))| Arc::ptr_eq(base_color, texture))
+            .map(|(path, _)| path.as_str())
+    }
+
+    pub fn load(
+        &mut self,
+        renderer: &RenderFrame,
+        graphics_command_pool: &GraphicsCommandPool,
+        world: &World,
+        path: &str,
+    ) -> Vec<Entity> {
+        self.spawn(renderer, graphics_command_pool, world, &Scene::open(path))
+    }
+
+    pub fn spawn(
+        &mut self,
+        renderer: &RenderFrame,
+        graphics_command_pool: &GraphicsCommandPool,
+        world: &World,
+        scene: &Scene,
+    ) -> Vec<Entity> {
+        let spawned = world
+            .entities_mut()
+            .allocate_many(scene.entities.len() as u32);
+        for (&entity, description) in spawned.iter().zip(scene.entities.iter()) {
+            let parent = description.parent.map(|parent| {
+                assert!(
+                    parent < spawned.len() && spawned[parent] != entity,
+                    "scene entity has an invalid parent {}",
+                    parent
+                );
+                spawned[parent]
+            });
+            let position = na::Point3::from(description.position);
+            let prefab = Prefab {
+                position: Some(position),
+                rotation: Some(description.rotation.to_quaternion(&position)),
+                scale: description.scale.as_ref().map(SceneScale::to_vector),
+                parent,
+                light: description.light.clone(),
+                mesh: description.mesh.clone(),
+                texture: description.texture.clone(),
+                ..Prefab::default()
+            };
+            PrefabInstance::new(prefab, |path| {
+                self.mesh(renderer, graphics_command_pool, path)
+            })
+            .insert(world, entity);
+        }
+
+        spawned
+    }
+}
+
+#[test]
+fn test_parse() {
+    let scene = Scene::parse(
+        r#"{
+            "entities": [
+                {"position": [0.0, 1.0, 0.0], "light": {"strength": 0.5}, "scale": 2.0},
+                {
+                    "parent": 0,
+                    "position": [0.0, 0.0, 0.0],
+                    "scale": [1.0, 2.0, 3.0],
+                    "rotation": {"axis_angle": {"axis": [0.0, 1.0, 0.0], "degrees": 90.0}},
+                    "mesh": "box.gltf"
+                }
+            ]
+        }"#,
+    );
+    assert_eq!(scene.entities.len(), 2);
+    assert_eq!(scene.entities[0].light.as_ref().unwrap().strength, 0.5);
+    assert!(scene.entities[0].mesh.is_none());
+    assert!(scene.entities[0].parent.is_none());
+    assert_eq!(scene.entities[1].parent, Some(0));
+    assert_eq!(scene.entities[1].mesh.as_ref().unwrap(), "box.gltf");
+    assert_eq!(
+        scene.entities[0].scale.as_ref().unwrap().to_vector(),
+        na::Vector3::repeat(2.0)
+    );
+    assert_eq!(
+        scene.entities[1].scale.as_ref().unwrap().to_vector(),
+        na::Vector3::new(1.0, 2.0, 3.0)
+    );
+
+    let rotation = scene.entities[1]
+        .rotation
+        .to_quaternion(&na::Point3::origin());
+    let rotated = rotation * na::Vector3::z();
+    assert!((rotated - na::Vector3::x()).norm() < 1e-5);
+}
+
+#[test]
+fn test_look_at() {
+    let position = na::Point3::new(5.0, 0.0, 0.0);
+    let rotation = SceneRotation::LookAt([0.0, 0.0, 0.0]).to_quaternion(&position);
+    assert!((rotation * -na::Vector3::x() - na::Vector3::z()).norm() < 1e-5);
+}
+
+#[test]
+fn test_main_scene() {
+    let scene = Scene::parse(include_str!("../../scenes/main.json"));
+    assert_eq!(scene.entities.len(), 30);
+    assert_eq!(
+        scene
+            .entities
+            .iter()
+            .filter(|entity| entity.light.is_some())
+            .count(),
+        2
+    );
+}
@@ -0,0 +1,163 @@
+use super::world::World;
+use std::{
+    any::{type_name, Any, TypeId},
+    fmt,
+};
+
+/// Declares what a system reads and writes, so that the `Schedule` can order it.
+pub struct Access {
+    name: &'static str,
+    reads: Vec<(TypeId, &'static str)>,
+    writes: Vec<(TypeId, &'static str)>,
+}
+
+impl Access {
+    pub fn new(name: &'static str) -> Access {
+        Access {
+            name,
+            reads: vec![],
+            writes: vec![],
+        }
+    }
+
+    pub fn reads<T: Any>(mut self) -> Access {
+        self.reads.push((TypeId::of::<T>(), type_name::<T>()));
+        self
+    }
+
+    pub fn writes<T: Any>(mut self) -> Access {
+        self.writes.push((TypeId::of::<T>(), type_name::<T>()));
+        self
+    }
+
+    fn conflicts_with(&self, other: &Access) -> bool {
+        let touches = |accesses: &[(TypeId, &'static str)], id: &TypeId| {
+            accesses.iter().any(|(other_id, _)| other_id == id)
+        };
+        self.writes
+            .iter()
+            .any(|(id, _)| touches(&other.reads, id) || touches(&other.writes, id))
+            || self.reads.iter().any(|(id, _)| touches(&other.writes, id))
+    }
+}
+
+struct System {
+    access: Access,
+    run: Box<dyn Fn(&World) + Send + Sync>,
+}
+
+/// Runs systems in stages. A system depends on every system added before it that it
+/// conflicts with, and lands in the first stage after all of its dependencies.
+/// Systems within a stage run concurrently on the rayon pool.
+pub struct Schedule {
+    systems: Vec<System>,
+    stages: Vec<Vec<usize>>,
+}
+
+impl Schedule {
+    pub fn new() -> Schedule {
+        Schedule {
+            systems: vec![],
+            stages: vec![],
+        }
+    }
+
+    pub fn add_system<F: Fn(&World) + Send + Sync + 'static>(
+        &mut self,
+        access: Access,
+        run: F,
+    ) -> &mut Schedule {
+        let stage = self
+            .stages
+            .iter()
+            .enumerate()
+            .filter(|(_, stage)| {
+                stage
+                    .iter()
+                    .any(|&ix| access.conflicts_with(&self.systems[ix].access))
+            })
+            .map(|(stage_ix, _)| stage_ix + 1)
+            .max()
+            .unwrap_or(0);
+        if stage == self.stages.len() {
+            self.stages.push(vec![]);
+        }
+        self.stages[stage].push(self.systems.len());
+        self.systems.push(System {
+            access,
+            run: Box::new(run),
+        });
+        self
+    }
+
+    pub fn run(&self, world: &World) {
+        for stage in self.stages.iter() {
+            match stage.as_slice() {
+                [ix] => (self.systems[*ix].run)(world),
+                _ => rayon::scope(|scope| {
+                    for &ix in stage.iter() {
+                        let system = &self.systems[ix];
+                        scope.spawn(move |_| (system.run)(world));
+                    }
+                }),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (stage_ix, stage) in self.stages.iter().enumerate() {
+            writeln!(f, "stage {}:", stage_ix)?;
+            for &ix in stage.iter() {
+                let access = &self.systems[ix].access;
+                writeln!(f, "  {}", access.name)?;
+                for (_, name) in access.reads.iter() {
+                    writeln!(f, "    reads {}", name)?;
+                }
+                for (_, name) in access.writes.iter() {
+                    writeln!(f, "    writes {}", name)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_stages() {
+    let mut schedule = Schedule::new();
+    schedule
+        .add_system(Access::new("a").reads::<u32>().writes::<f32>(), |_| ())
+        .add_system(Access::new("b").reads::<u32>().writes::<u64>(), |_| ())
+        .add_system(Access::new("c").reads::<f32>().writes::<i32>(), |_| ())
+        .add_system(Access::new("d").writes::<u32>(), |_| ())
+        .add_system(Access::new("e").reads::<i64>(), |_| ());
+    assert_eq!(schedule.stages, vec![vec![0, 1, 4], vec![2, 3]]);
+}
+
+#[test]
+fn test_run() {
+    let mut schedule = Schedule::new();
+    schedule
+        .add_system(Access::new("double").writes::<u32>(), |world| {
+            *world.write::<u32>() *= 2;
+        })
+        .add_system(
+            Access::new("convert").reads::<u32>().writes::<f32>(),
+            |world| {
+                *world.write::<f32>() = *world.read::<u32>() as f32;
+            },
+        )
+        .add_system(Access::new("increment").writes::<u64>(), |world| {
+            *world.write::<u64>() += 1;
+        });
+    let mut world = World::new();
+    world.insert(3u32);
+    world.insert(0f32);
+    world.insert(0u64);
+    schedule.run(&world);
+    assert_eq!(*world.read::<u32>(), 6);
+    assert_eq!(*world.read::<f32>(), 6.0);
+    assert_eq!(*world.read::<u64>(), 1);
+}
@@ -0,0 +1,275 @@
+use super::{components::*, custom::*, scene::SceneLoader, world::World};
+use crate::renderer::{GltfMesh, GltfMeshBaseColorTexture, GraphicsCommandPool, RenderFrame};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+};
+
+#[cfg(test)]
+use super::prefab::test_world;
+
+/// Saved and loaded on request of the debug GUI
+pub const SNAPSHOT_PATH: &str = "snapshot.json";
+
+/// Serializable copy of the simulation state of a `World`. Components are stored as
+/// `(entity id, value)` pairs, meshes and textures as the paths they were loaded from.
+/// Generations are not saved, restoring keeps counting from the current ones.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct Snapshot {
+    pub entities: Vec<u32>,
+    pub positions: Vec<(u32, [f32; 3])>,
+    /// Quaternion coordinates in `[i, j, k, w]` order
+    pub rotations: Vec<(u32, [f32; 4])>,
+    pub scales: Vec<(u32, [f32; 3])>,
+    /// Only links to live entities are saved
+    pub parents: Vec<(u32, u32)>,
+    pub lights: Vec<(u32, Light)>,
+    pub projectile_targets: Vec<(u32, [f32; 3])>,
+    pub projectile_velocities: Vec<(u32, f32)>,
+    pub meshes: Vec<(u32, String)>,
+    pub textures: Vec<(u32, String)>,
+}
+
+fn capture_storage<T, S, F: Fn(&T) -> S>(
+    entities: &EntitiesStorage,
+    storage: &ComponentStorage<T>,
+    f: F,
+) -> Vec<(u32, S)> {
+    join((entities, storage))
+        .map(|(entity_id, component)| (entity_id, f(component)))
+        .collect()
+}
+
+fn restore_storage<T, S, F: FnMut(&S) -> T>(
+    storage: &mut ComponentStorage<T>,
+    values: &[(u32, S)],
+    mut f: F,
+) {
+    for (entity_id, value) in values {
+        storage.insert(*entity_id, f(value));
+    }
+}
+
+fn point_to_array(point: &na::Point3<f32>) -> [f32; 3] {
+    [point.x, point.y, point.z]
+}
+
+impl Snapshot {
+    /// Asset references are resolved through the `SceneLoader` every mesh was loaded with
+    pub fn capture(world: &World, scene_loader: &SceneLoader) -> Snapshot {
+        let entities = world.entities();
+        Snapshot {
+            entities: entities.mask().to_vec(),
+            positions: capture_storage(
+                &entities,
+                &world.storage::<na::Point3<f32>>(),
+                point_to_array,
+            ),
+            rotations: capture_storage(
+                &entities,
+                &world.storage::<na::UnitQuaternion<f32>>(),
+                |rotation| {
+                    let coords = rotation.quaternion().coords;
+                    [coords.x, coords.y, coords.z, coords.w]
+                },
+            ),
+            scales: capture_storage(&entities, &world.storage::<Scale>(), |scale| {
+                [scale.0.x, scale.0.y, scale.0.z]
+            }),
+            parents: join((&*entities, &*world.storage::<Parent>()))
+                .filter(|(_, parent)| entities.is_alive(parent.0))
+                .map(|(entity_id, parent)| (entity_id, parent.0.index))
+                .collect(),
+            lights: capture_storage(&entities, &world.storage::<Light>(), Light::clone),
+            projectile_targets: capture_storage(
+                &entities,
+                &world.storage::<ProjectileTarget>(),
+                |target| point_to_array(&target.0),
+            ),
+            projectile_velocities: capture_storage(
+                &entities,
+                &world.storage::<ProjectileVelocity>(),
+                |velocity| velocity.0,
+            ),
+            meshes: capture_storage(&entities, &world.storage::<GltfMesh>(), |mesh| {
+                scene_loader
+                    .mesh_path(mesh)
+                    .expect("mesh was not loaded through the SceneLoader")
+                    .to_string()
+            }),
+            textures: capture_storage(
+                &entities,
+                &world.storage::<GltfMeshBaseColorTexture>(),
+                |texture| {
+                    scene_loader
+                        .texture_path(&texture.0)
+                        .expect("texture was not loaded through the SceneLoader")
+                        .to_string()
+                },
+            ),
+        }
+    }
+
+    pub fn save(&self, path: &str) {
+        let file = File::create(path)
+            .unwrap_or_else(|err| panic!("failed to create snapshot {}: {}", path, err));
+        serde_json::to_writer(BufWriter::new(file), self)
+            .unwrap_or_else(|err| panic!("failed to write snapshot {}: {}", path, err));
+    }
+
+    pub fn open(path: &str) -> Snapshot {
+        let file = File::open(path)
+            .unwrap_or_else(|err| panic!("failed to open snapshot {}: {}", path, err));
+        serde_json::from_reader(BufReader::new(file))
+            .unwrap_or_else(|err| panic!("failed to parse snapshot {}: {}", path, err))
+    }
+
+    /// Replaces every entity in the `World` with the ones in the snapshot, restoring all the
+    /// components but the meshes and textures, see `restore_assets()`. The entities are freed
+    /// with `World::maintain_entities()`, so the removals show up next to the changes of the
+    /// current frame.
+    pub fn restore(&self, world: &World) {
+        {
+            let mut entities = world.entities_mut();
+            let alive = entities.mask().clone();
+            for entity_id in alive.iter() {
+                entities.remove(entity_id);
+            }
+        }
+        world.maintain_entities();
+
+        let mut ids = croaring::Bitmap::create();
+        ids.add_many(&self.entities);
+        world.entities_mut().allocate_ids(&ids);
+
+        restore_storage(
+            &mut world.storage_mut::<na::Point3<f32>>(),
+            &self.positions,
+            |position| na::Point3::from(*position),
+        );
+        restore_storage(
+            &mut world.storage_mut::<na::UnitQuaternion<f32>>(),
+            &self.rotations,
+            |&[i, j, k, w]| na::UnitQuaternion::new_unchecked(na::Quaternion::new(w, i, j, k)),
+        );
+        restore_storage(&mut world.storage_mut::<Scale>(), &self.scales, |scale| {
+            Scale(na::Vector3::from(*scale))
+        });
+        restore_storage(
+            &mut world.storage_mut::<Parent>(),
+            &self.parents,
+            |parent| Parent(world.entities().entity(*parent)),
+        );
+        restore_storage(
+            &mut world.storage_mut::<Light>(),
+            &self.lights,
+            Light::clone,
+        );
+        restore_storage(
+            &mut world.storage_mut::<ProjectileTarget>(),
+            &self.projectile_targets,
+            |target| ProjectileTarget(na::Point3::from(*target)),
+        );
+        restore_storage(
+            &mut world.storage_mut::<ProjectileVelocity>(),
+            &self.projectile_velocities,
+            |velocity| ProjectileVelocity(*velocity),
+        );
+    }
+
+    /// Loads the referenced meshes and textures, the ones already loaded are shared
+    pub fn restore_assets(
+        &self,
+        renderer: &RenderFrame,
+        graphics_command_pool: &GraphicsCommandPool,
+        world: &World,
+        scene_loader: &mut SceneLoader,
+    ) {
+        restore_storage(&mut world.storage_mut::<GltfMesh>(), &self.meshes, |path| {
+            scene_loader.mesh(renderer, graphics_command_pool, path).0
+        });
+        restore_storage(
+            &mut world.storage_mut::<GltfMeshBaseColorTexture>(),
+            &self.textures,
+            |path| {
+                GltfMeshBaseColorTexture(scene_loader.mesh(renderer, graphics_command_pool, path).1)
+            },
+        );
+    }
+}
+
+#[test]
+fn test_round_trip() {
+    let world = test_world();
+    {
+        let mut entities = world.entities_mut();
+        let mut positions = world.storage_mut::<na::Point3<f32>>();
+        let mut rotations = world.storage_mut::<na::UnitQuaternion<f32>>();
+        let mut scales = world.storage_mut::<Scale>();
+        let mut parents = world.storage_mut::<Parent>();
+        let mut lights = world.storage_mut::<Light>();
+        let mut targets = world.storage_mut::<ProjectileTarget>();
+        let mut velocities = world.storage_mut::<ProjectileVelocity>();
+        let ixes = entities.allocate_many(4);
+        for (ix, entity) in ixes.iter().enumerate() {
+            positions.insert(*entity, na::Point3::new(ix as f32, 1.0, 2.0));
+            rotations.insert(
+                *entity,
+                na::UnitQuaternion::from_axis_angle(&na::Vector3::y_axis(), ix as f32),
+            );
+        }
+        lights.insert(ixes[0], Light { strength: 0.5 });
+        scales.insert(ixes[3], Scale(na::Vector3::new(1.0, 2.0, 3.0)));
+        parents.insert(ixes[3], Parent(ixes[0]));
+        // points to a removed entity, must not be saved
+        parents.insert(ixes[2], Parent(ixes[1]));
+        targets.insert(ixes[2], ProjectileTarget(na::Point3::new(0.0, 0.0, 100.0)));
+        velocities.insert(ixes[2], ProjectileVelocity(20.0));
+        // removed but not maintained yet, must not be saved
+        entities.remove(ixes[1]);
+    }
+    let saved = Snapshot::capture(&world, &SceneLoader::new());
+    assert_eq!(saved.entities, vec![0, 2, 3]);
+    assert_eq!(saved.positions.len(), 3);
+    assert_eq!(saved.parents, vec![(3, 0)]);
+
+    let serialized = serde_json::to_string(&saved).unwrap();
+    let loaded: Snapshot = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(loaded, saved);
+
+    let restored = test_world();
+    restored.entities_mut().allocate_many(10);
+    loaded.restore(&restored);
+    assert_eq!(restored.entities().mask().to_vec(), vec![0, 2, 3]);
+    assert_eq!(
+        restored.storage::<na::Point3<f32>>().mask().to_vec(),
+        vec![0, 2, 3]
+    );
+    assert_eq!(restored.storage::<Light>().mask().to_vec(), vec![0]);
+    assert_eq!(
+        *restored.storage::<Scale>().get(3).unwrap(),
+        Scale(na::Vector3::new(1.0, 2.0, 3.0))
+    );
+    assert_eq!(
+        restored.storage::<Parent>().get(3).unwrap().0,
+        restored.entities().entity(0)
+    );
+    assert_eq!(
+        restored.storage::<ProjectileTarget>().get(2).unwrap().0,
+        na::Point3::new(0.0, 0.0, 100.0)
+    );
+    assert_eq!(
+        restored.storage::<ProjectileVelocity>().get(2).unwrap().0,
+        20.0
+    );
+    let original = world.storage::<na::UnitQuaternion<f32>>();
+    let rotations = restored.storage::<na::UnitQuaternion<f32>>();
+    for entity_id in [0, 2, 3].iter() {
+        assert_eq!(
+            rotations.get(*entity_id).unwrap(),
+            original.get(*entity_id).unwrap()
+        );
+    }
+    assert_eq!(Snapshot::capture(&restored, &SceneLoader::new()), saved);
+}
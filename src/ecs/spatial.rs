@@ -0,0 +1,236 @@
+use super::custom::*;
+use hashbrown::HashMap;
+#[cfg(feature = "microprofile")]
+use microprofile::scope;
+use ncollide3d::{
+    bounding_volume::{BoundingVolume, AABB},
+    partitioning::{DBVTLeaf, DBVTLeafId, VisitStatus, Visitor, BVH, DBVT},
+    query::{Ray, RayCast},
+};
+
+/// Leaves are enlarged by this much, so that entities moving a little don't need to be
+/// reinserted every frame
+const LEAF_MARGIN: f32 = 0.5;
+
+struct Leaf {
+    id: DBVTLeafId,
+    entity: Entity,
+    aabb: AABB<f32>,
+}
+
+/// Dynamic bounding volume tree over the world AABBs of entities, kept up to date by
+/// `UpdateSpatialIndex`. Queries descend only into the subtrees that can contain matches and
+/// check the exact AABB of each candidate.
+pub struct SpatialIndex {
+    tree: DBVT<f32, u32, AABB<f32>>,
+    leaves: HashMap<u32, Leaf>,
+}
+
+impl SpatialIndex {
+    pub fn new() -> SpatialIndex {
+        SpatialIndex {
+            tree: DBVT::new(),
+            leaves: HashMap::new(),
+        }
+    }
+
+    /// Inserts the entity or moves it to `aabb`
+    pub fn update(&mut self, entity: Entity, aabb: &AABB<f32>) {
+        let tree = &mut self.tree;
+        let leaf = self.leaves.entry(entity.index).or_insert_with(|| Leaf {
+            id: tree.insert(DBVTLeaf::new(aabb.loosened(LEAF_MARGIN), entity.index)),
+            entity,
+            aabb: aabb.clone(),
+        });
+        leaf.entity = entity;
+        leaf.aabb = aabb.clone();
+        if !tree[leaf.id].bounding_volume.contains(aabb) {
+            tree.remove(leaf.id);
+            leaf.id = tree.insert(DBVTLeaf::new(aabb.loosened(LEAF_MARGIN), entity.index));
+        }
+    }
+
+    pub fn remove(&mut self, entity_id: u32) {
+        if let Some(leaf) = self.leaves.remove(&entity_id) {
+            self.tree.remove(leaf.id);
+        }
+    }
+
+    fn query<F: Fn(&AABB<f32>) -> bool>(&self, overlaps: F) -> Vec<Entity> {
+        let mut visitor = QueryVisitor {
+            leaves: &self.leaves,
+            overlaps,
+            found: vec![],
+        };
+        self.tree.visit(&mut visitor);
+        visitor.found
+    }
+
+    /// Entities not fully outside of any of the planes, see `Camera::frustum_planes`
+    pub fn frustum(&self, planes: &[na::Vector4<f32>; 6]) -> Vec<Entity> {
+        self.query(|aabb| !outside_frustum(planes, aabb))
+    }
+
+    /// Entities hit by the ray within `max_toi`, nearest first
+    pub fn ray(&self, ray: &Ray<f32>, max_toi: f32) -> Vec<(Entity, f32)> {
+        let toi = |aabb: &AABB<f32>| {
+            aabb.toi_with_ray(&na::Isometry3::identity(), ray, true)
+                .filter(|toi| *toi <= max_toi)
+        };
+        let mut hits: Vec<(Entity, f32)> = self
+            .query(|aabb| toi(aabb).is_some())
+            .into_iter()
+            .filter_map(|entity| toi(&self.leaves[&entity.index].aabb).map(|toi| (entity, toi)))
+            .collect();
+        hits.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+        hits
+    }
+
+    /// Entities within `radius` of `center`
+    pub fn sphere(&self, center: &na::Point3<f32>, radius: f32) -> Vec<Entity> {
+        self.query(|aabb| {
+            let closest = glm::clamp_vec(&center.coords, &aabb.mins().coords, &aabb.maxs().coords);
+            (closest - center.coords).norm_squared() <= radius * radius
+        })
+    }
+
+    /// Entities overlapping `aabb`
+    pub fn aabb(&self, aabb: &AABB<f32>) -> Vec<Entity> {
+        self.query(|other| other.intersects(aabb))
+    }
+}
+
+struct QueryVisitor<'a, F> {
+    leaves: &'a HashMap<u32, Leaf>,
+    overlaps: F,
+    found: Vec<Entity>,
+}
+
+impl<'a, F: Fn(&AABB<f32>) -> bool> Visitor<u32, AABB<f32>> for QueryVisitor<'a, F> {
+    fn visit(&mut self, bounding_volume: &AABB<f32>, entity_id: Option<&u32>) -> VisitStatus {
+        if !(self.overlaps)(bounding_volume) {
+            return VisitStatus::Stop;
+        }
+        if let Some(entity_id) = entity_id {
+            let leaf = &self.leaves[entity_id];
+            // the tree only holds the enlarged bounds
+            if (self.overlaps)(&leaf.aabb) {
+                self.found.push(leaf.entity);
+            }
+        }
+        VisitStatus::Continue
+    }
+}
+
+/// Whether the AABB is entirely on the outer side of one of the planes
+pub fn outside_frustum(planes: &[na::Vector4<f32>; 6], aabb: &AABB<f32>) -> bool {
+    planes.iter().any(|plane| {
+        let e = aabb.half_extents().dot(&plane.xyz().abs());
+        let s = plane.dot(&aabb.center().to_homogeneous());
+        s - e > 0.0
+    })
+}
+
+/// Applies the AABB changes of the frame to the `SpatialIndex`, needs to run after
+/// `AABBCalculation`
+pub struct UpdateSpatialIndex;
+
+impl UpdateSpatialIndex {
+    pub fn exec(
+        entities: &EntitiesStorage,
+        aabbs: &ComponentStorage<AABB<f32>>,
+        spatial_index: &mut SpatialIndex,
+    ) {
+        #[cfg(feature = "profiling")]
+        microprofile::scope!("ecs", "update spatial index");
+        // replaced components are in both, so removals go first
+        for entity_id in aabbs.removed().iter() {
+            spatial_index.remove(entity_id);
+        }
+        for (entity_id, _, aabb) in join((aabbs.modified(), entities, aabbs)) {
+            spatial_index.update(entities.entity(entity_id), aabb);
+        }
+    }
+}
+
+#[cfg(test)]
+fn cube(x: f32, y: f32, z: f32) -> AABB<f32> {
+    AABB::from_half_extents(na::Point3::new(x, y, z), na::Vector3::repeat(0.5))
+}
+
+#[test]
+fn test_update_spatial_index() {
+    let mut entities = EntitiesStorage::new();
+    let mut aabbs = ComponentStorage::new();
+    let mut spatial_index = SpatialIndex::new();
+    let (first, second) = (entities.allocate(), entities.allocate());
+    aabbs.insert(first, cube(0.0, 0.0, 0.0));
+    aabbs.insert(second, cube(10.0, 0.0, 0.0));
+    UpdateSpatialIndex::exec(&entities, &aabbs, &mut spatial_index);
+    assert_eq!(
+        spatial_index.sphere(&na::Point3::origin(), 1.0),
+        vec![first]
+    );
+
+    // within the margin, the exact bounds are still used
+    aabbs.clear_changes();
+    *aabbs.get_mut(first).unwrap() = cube(0.3, 0.0, 0.0);
+    UpdateSpatialIndex::exec(&entities, &aabbs, &mut spatial_index);
+    assert!(spatial_index.aabb(&cube(-0.9, 0.0, 0.0)).is_empty());
+    *aabbs.get_mut(first).unwrap() = cube(9.0, 0.0, 0.0);
+    UpdateSpatialIndex::exec(&entities, &aabbs, &mut spatial_index);
+    assert!(spatial_index.sphere(&na::Point3::origin(), 1.0).is_empty());
+    let mut near = spatial_index.sphere(&na::Point3::new(9.5, 0.0, 0.0), 0.5);
+    near.sort_by_key(|entity| entity.index);
+    assert_eq!(near, vec![first, second]);
+
+    aabbs.clear_changes();
+    entities.remove(second);
+    aabbs.maintain(&entities.maintain());
+    UpdateSpatialIndex::exec(&entities, &aabbs, &mut spatial_index);
+    assert_eq!(spatial_index.aabb(&cube(9.5, 0.0, 0.0)), vec![first]);
+}
+
+#[test]
+fn test_spatial_index_queries() {
+    let mut entities = EntitiesStorage::new();
+    let mut spatial_index = SpatialIndex::new();
+    let row: Vec<Entity> = (0..50)
+        .map(|x| {
+            let entity = entities.allocate();
+            spatial_index.update(entity, &cube(2.0 * x as f32, 0.0, 0.0));
+            entity
+        })
+        .collect();
+
+    let ray = Ray::new(na::Point3::new(-5.0, 0.0, 0.0), na::Vector3::x());
+    let hits = spatial_index.ray(&ray, 9.0);
+    assert_eq!(
+        hits.iter().map(|(entity, _)| *entity).collect::<Vec<_>>(),
+        vec![row[0], row[1], row[2]]
+    );
+    assert!((hits[1].1 - 6.5).abs() < 1e-5);
+    let above = Ray::new(na::Point3::new(-5.0, 2.0, 0.0), na::Vector3::x());
+    assert!(spatial_index.ray(&above, 1000.0).is_empty());
+
+    // just the 10th to 20th cube, between the planes x >= 19 and x <= 39
+    let zero = na::Vector4::zeros();
+    let planes = [
+        na::Vector4::new(-1.0, 0.0, 0.0, 19.0),
+        na::Vector4::new(1.0, 0.0, 0.0, -39.0),
+        zero,
+        zero,
+        zero,
+        zero,
+    ];
+    let mut visible = spatial_index.frustum(&planes);
+    visible.sort_by_key(|entity| entity.index);
+    assert_eq!(visible, row[10..20].to_vec());
+
+    let mut overlapping = spatial_index.aabb(&AABB::new(
+        na::Point3::new(2.0, -1.0, -1.0),
+        na::Point3::new(5.0, 1.0, 1.0),
+    ));
+    overlapping.sort_by_key(|entity| entity.index);
+    assert_eq!(overlapping, vec![row[1], row[2]]);
+}
@@ -1,48 +1,142 @@
-use super::{super::renderer::*, custom::*, resources::*};
+use super::{
+    super::renderer::*, bindings::*, commands::*, components::*, custom::*, gizmo::Gizmo,
+    hierarchy::*, inspector::*, prefab::*, spatial::SpatialIndex, world::World,
+};
 use imgui::im_str;
 use imgui_winit_support::WinitPlatform;
 #[cfg(feature = "microprofile")]
 use microprofile::scope;
 use na::RealField;
 use parking_lot::Mutex;
+use rayon::prelude::*;
+use serde_derive::{Deserialize, Serialize};
 use std::{sync::Arc, time::Instant};
 use winit::{
     self,
-    dpi::PhysicalSize,
+    dpi::{PhysicalPosition, PhysicalSize},
     event::{
         ButtonId, DeviceEvent, ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent,
     },
     platform::desktop::EventLoopExtDesktop,
 };
 
-use crate::renderer::{
-    forward_vector, right_vector, up_vector, GltfMesh, GltfMeshBaseColorTexture, Swapchain,
-};
+use crate::renderer::{forward_vector, right_vector, up_vector, GltfMesh, Swapchain};
 
+/// Calculates world matrices, children are placed relative to the model matrix of their `Parent`.
+/// Entities with a `PreviousTransform` are interpolated between the last two simulation steps.
 pub struct ModelMatrixCalculation;
 
 impl ModelMatrixCalculation {
+    #[allow(clippy::too_many_arguments)]
     pub fn exec(
         entities: &EntitiesStorage,
+        frame_timing: &FrameTiming,
         positions: &ComponentStorage<na::Point3<f32>>,
         rotations: &ComponentStorage<na::UnitQuaternion<f32>>,
-        scales: &ComponentStorage<f32>,
+        scales: &ComponentStorage<Scale>,
+        parents: &ComponentStorage<Parent>,
+        previous_transforms: &ComponentStorage<PreviousTransform>,
         model_matrices: &mut ComponentStorage<glm::Mat4>,
     ) {
         #[cfg(feature = "profiling")]
         microprofile::scope!("ecs", "model matrix calculation");
-        model_matrices
-            .replace_mask(&(entities.mask() & positions.mask() & rotations.mask() & scales.mask()));
+        let mask = (entities, positions, rotations, scales)
+            .join_mask()
+            .into_owned();
+        // newly joined entities get filled in with identity and marked modified
+        model_matrices.replace_mask_with(&mask, glm::Mat4::identity);
+        let mut changed = croaring::Bitmap::fast_or(&[
+            positions.modified(),
+            rotations.modified(),
+            scales.modified(),
+            parents.modified(),
+            parents.removed(),
+            // interpolated ones move every frame
+            previous_transforms.mask(),
+            previous_transforms.removed(),
+            model_matrices.modified(),
+        ]);
+        changed.and_inplace(&mask);
+        // children of despawned parents, or ones that lost their model matrix, become roots
+        if !model_matrices.removed().is_empty() {
+            for (entity_id, parent) in join((&mask, parents)) {
+                if model_matrices.removed().contains(parent.0.index) {
+                    changed.add(entity_id);
+                }
+            }
+        }
+        let order = hierarchy_order(entities, parents, &mask);
+        propagate_changes(&order, &mut changed);
 
-        for entity_id in model_matrices.mask().clone().iter() {
-            let pos = positions.get(entity_id).unwrap();
-            let rot = rotations.get(entity_id).unwrap();
-            let scale = scales.get(entity_id).unwrap();
-            *model_matrices
-                .entry(entity_id)
-                .or_insert(glm::Mat4::identity()) = glm::translation(&pos.coords)
-                * rot.to_homogeneous()
-                * glm::scaling(&glm::Vec3::repeat(*scale));
+        let alpha = frame_timing.alpha();
+        par_join((&changed, positions, rotations, scales, &mut *model_matrices)).for_each(
+            |(entity_id, pos, rot, scale, model_matrix)| {
+                let (pos, rot) = if previous_transforms.mask().contains(entity_id) {
+                    let previous = previous_transforms.get(entity_id).unwrap();
+                    (
+                        previous.position.coords.lerp(&pos.coords, alpha),
+                        previous
+                            .rotation
+                            .try_slerp(rot, alpha, 1.0e-6)
+                            .unwrap_or(*rot),
+                    )
+                } else {
+                    (pos.coords, *rot)
+                };
+                *model_matrix =
+                    glm::translation(&pos) * rot.to_homogeneous() * glm::scaling(&scale.0);
+            },
+        );
+        propagate_world_matrices(&order, &changed, model_matrices);
+    }
+}
+
+/// Depth is reversed for every kind of projection, 1.0 at the near plane and 0.0 at the far
+/// plane, so the depth buffer setup doesn't change with it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProjectionMode {
+    /// `fov_y` is in degrees
+    Perspective { fov_y: f32, near: f32, far: f32 },
+    /// Perspective without a far plane, depth approaches 0.0 at infinity
+    InfinitePerspective { fov_y: f32, near: f32 },
+    /// `height` is the vertical extent of the view volume in world units
+    Orthographic { height: f32, near: f32, far: f32 },
+}
+
+impl ProjectionMode {
+    pub fn matrix(&self, aspect: f32) -> glm::Mat4 {
+        // maps depth d to 1 - d
+        #[rustfmt::skip]
+        let reverse_z = glm::mat4(
+            1.0, 0.0, 0.0, 0.0,
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, -1.0, 1.0,
+            0.0, 0.0, 0.0, 1.0,
+        );
+        match *self {
+            ProjectionMode::Perspective { fov_y, near, far } => {
+                reverse_z * glm::perspective_lh_zo(aspect, fov_y.to_radians(), near, far)
+            }
+            ProjectionMode::InfinitePerspective { fov_y, near } => {
+                // limit of the reversed perspective above as far goes to infinity
+                let mut projection =
+                    glm::perspective_lh_zo(aspect, fov_y.to_radians(), near, 2.0 * near);
+                projection[(2, 2)] = 0.0;
+                projection[(2, 3)] = near;
+                projection
+            }
+            ProjectionMode::Orthographic { height, near, far } => {
+                let (half_width, half_height) = (height * aspect / 2.0, height / 2.0);
+                reverse_z
+                    * glm::ortho_lh_zo(
+                        -half_width,
+                        half_width,
+                        -half_height,
+                        half_height,
+                        near,
+                        far,
+                    )
+            }
         }
     }
 }
@@ -50,9 +144,11 @@ impl ModelMatrixCalculation {
 pub struct Camera {
     pub position: na::Point3<f32>,
     pub rotation: na::UnitQuaternion<f32>,
+    pub projection_mode: ProjectionMode,
     pub projection: na::Matrix4<f32>,
     pub view: na::Matrix4<f32>,
-    // left -> right -> bottom -> top -> near -> far
+    // left -> right -> bottom -> top -> near -> far, the far plane of an infinite perspective
+    // never culls anything
     pub frustum_planes: [na::Vector4<f32>; 6],
 }
 
@@ -67,6 +163,10 @@ impl Default for Camera {
         Camera {
             position,
             rotation,
+            projection_mode: ProjectionMode::InfinitePerspective {
+                fov_y: 70.0,
+                near: 0.1,
+            },
             projection,
             view,
             frustum_planes: [zero; 6],
@@ -87,16 +187,12 @@ impl InputHandler {
         window: &winit::window::Window,
         gui: &mut imgui::Context,
         input_state: &mut InputState,
-        camera: &mut Camera,
-        runtime_config: &mut RuntimeConfiguration,
     ) -> bool {
         #[cfg(feature = "profiling")]
         microprofile::scope!("ecs", "input handler");
         let quit_handle = Arc::clone(&self.quit_handle);
         input_state.clear();
-        let fly_mode = runtime_config.fly_mode;
         let platform = &mut self.imgui_platform;
-        let mut toggle_fly_mode = false;
         let mut resized = false;
         // println!("event loop run_return");
         self.events_loop
@@ -139,48 +235,44 @@ impl InputHandler {
                                 ..
                             },
                         ..
+                    } => match state {
+                        ElementState::Pressed => input_state.key_presses.push(virtual_keycode),
+                        ElementState::Released => input_state.key_releases.push(virtual_keycode),
+                    },
+                    Event::WindowEvent {
+                        event:
+                            WindowEvent::CursorMoved {
+                                position: PhysicalPosition { x, y },
+                                ..
+                            },
+                        ..
                     } => {
-                        match state {
-                            ElementState::Pressed => input_state.key_presses.push(virtual_keycode),
-                            ElementState::Released => {
-                                input_state.key_releases.push(virtual_keycode)
-                            }
-                        }
-                        match virtual_keycode {
-                            Some(VirtualKeyCode::G) if state == ElementState::Pressed => {
-                                toggle_fly_mode = true;
-                            }
-                            Some(VirtualKeyCode::Escape) => {
-                                *quit_handle.lock() = true;
-                            }
-                            _ => (),
-                        }
+                        input_state.cursor_position = Some((x as f32, y as f32));
                     }
-                    Event::DeviceEvent {
-                        event: DeviceEvent::MouseMotion { delta: (x, y), .. },
+                    Event::WindowEvent {
+                        event: WindowEvent::CursorLeft { .. },
                         ..
-                    } if fly_mode => {
-                        let y_angle = f32::pi() / 180.0 * y as f32;
-                        let x_angle = f32::pi() / 180.0 * x as f32;
-                        camera.rotation *= na::Rotation3::from_axis_angle(&right_vector(), y_angle);
-                        camera.rotation =
-                            na::Rotation3::from_axis_angle(&up_vector(), x_angle) * camera.rotation;
+                    } => {
+                        input_state.cursor_position = None;
                     }
                     Event::DeviceEvent {
-                        event:
-                            DeviceEvent::Button {
-                                button,
-                                state: ElementState::Pressed,
-                            },
+                        event: DeviceEvent::MouseMotion { delta: (x, y), .. },
                         ..
                     } => {
-                        input_state.button_presses.push(button);
+                        input_state.mouse_delta.0 += x as f32;
+                        input_state.mouse_delta.1 += y as f32;
                     }
+                    Event::DeviceEvent {
+                        event: DeviceEvent::Button { button, state },
+                        ..
+                    } => match state {
+                        ElementState::Pressed => input_state.button_presses.push(button),
+                        ElementState::Released => input_state.button_releases.push(button),
+                    },
                     _ => (),
                 };
                 *control_flow = winit::event_loop::ControlFlow::Exit;
             });
-        runtime_config.fly_mode = if toggle_fly_mode { !fly_mode } else { fly_mode };
         platform
             .prepare_frame(gui.io_mut(), &window)
             .expect("Failed to prepare frame");
@@ -195,12 +287,11 @@ impl ProjectCamera {
     pub fn exec(swapchain: &Swapchain, camera: &mut Camera) {
         #[cfg(feature = "profiling")]
         microprofile::scope!("ecs", "project camera");
-        let near = 0.1;
-        let far = 100.0;
-        let aspect = swapchain.width as f32 / swapchain.height as f32;
-        let fovy = glm::radians(&glm::vec1(70.0));
+        ProjectCamera::project(swapchain.width as f32 / swapchain.height as f32, camera);
+    }
 
-        camera.projection = glm::perspective_lh_zo(aspect, fovy.x, near, far);
+    pub fn project(aspect: f32, camera: &mut Camera) {
+        camera.projection = camera.projection_mode.matrix(aspect);
 
         let dir = camera.rotation.transform_vector(&forward_vector());
         let extended_forward = camera.position + dir;
@@ -208,21 +299,32 @@ impl ProjectCamera {
 
         camera.view = glm::look_at_lh(&camera.position.coords, &extended_forward.coords, &up);
 
+        // inside is -w <= x <= w, -w <= y <= w and 0 <= z <= w in clip space, with the reversed
+        // depth the near plane is at z = w
         let m = camera.projection * camera.view;
         camera.frustum_planes = [
             -(m.row(3) + m.row(0)).transpose(),
             -(m.row(3) - m.row(0)).transpose(),
             -(m.row(3) + m.row(1)).transpose(),
             -(m.row(3) - m.row(1)).transpose(),
-            -(m.row(3) + m.row(2)).transpose(),
             -(m.row(3) - m.row(2)).transpose(),
+            -m.row(2).transpose(),
         ];
     }
 }
 
+/// Length of a simulation step in seconds, simulation systems only ever advance by this much
+pub const FIXED_TIME_STEP: f32 = 1.0 / 60.0;
+/// Caps the catching up after a stall, the rest of the stall is dropped
+const MAX_STEPS_PER_FRAME: u32 = 8;
+
 pub struct FrameTiming {
     previous_frame: Instant,
     time_delta: f32,
+    // time not simulated yet, always less than a step
+    accumulator: f32,
+    steps: u32,
+    alpha: f32,
 }
 
 impl Default for FrameTiming {
@@ -230,22 +332,51 @@ impl Default for FrameTiming {
         FrameTiming {
             previous_frame: Instant::now(),
             time_delta: 0.0,
+            accumulator: 0.0,
+            steps: 0,
+            alpha: 0.0,
         }
     }
 }
 
+impl FrameTiming {
+    /// Wall-clock duration of the last frame
+    pub fn time_delta(&self) -> f32 {
+        self.time_delta
+    }
+
+    /// Number of simulation steps to run this frame
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+
+    /// How far between the last two simulation steps the frame is rendered, in `[0, 1)`
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+
+    pub fn advance(&mut self, time_delta: f32) {
+        self.time_delta = time_delta;
+        self.accumulator += time_delta;
+        let due = (self.accumulator / FIXED_TIME_STEP).floor();
+        self.accumulator -= due * FIXED_TIME_STEP;
+        self.steps = (due as u32).min(MAX_STEPS_PER_FRAME);
+        self.alpha = (self.accumulator / FIXED_TIME_STEP).min(1.0);
+    }
+}
+
 pub struct CalculateFrameTiming;
 
 impl CalculateFrameTiming {
     pub fn exec(frame_timing: &mut FrameTiming) {
         let now = Instant::now();
         let duration = now - frame_timing.previous_frame;
-        frame_timing.time_delta =
-            duration.as_secs() as f32 + (duration.subsec_micros() as f32 / 1e6);
+        frame_timing.advance(duration.as_secs() as f32 + (duration.subsec_micros() as f32 / 1e6));
         frame_timing.previous_frame = now;
     }
 }
 
+/// Bounds meshes in world space, using the model matrices propagated through the hierarchy
 pub struct AABBCalculation;
 
 impl AABBCalculation {
@@ -257,65 +388,86 @@ impl AABBCalculation {
     ) {
         #[cfg(feature = "profiling")]
         microprofile::scope!("ecs", "aabb calculation");
-        use std::f32::{MAX, MIN};
-        let desired = entities.mask() & model_matrices.mask() & meshes.mask();
-        aabb.replace_mask(&desired);
-        for entity_id in desired.iter() {
-            let model_matrix = model_matrices.get(entity_id).unwrap();
-            let mesh = meshes.get(entity_id).unwrap();
-            let min = mesh.aabb.mins();
-            let max = mesh.aabb.maxs();
-            let (min, max) = [
-                // bottom half (min y)
-                na::Point3::new(min.x, min.y, min.z),
-                na::Point3::new(max.x, min.y, min.z),
-                na::Point3::new(min.x, min.y, max.z),
-                na::Point3::new(max.x, min.y, max.z),
-                // top half (max y)
-                na::Point3::new(min.x, max.y, min.z),
-                na::Point3::new(max.x, max.y, min.z),
-                na::Point3::new(min.x, max.y, max.z),
-                na::Point3::new(max.x, max.y, max.z),
-            ]
-            .iter()
-            .map(|vertex| model_matrix * vertex.to_homogeneous())
-            .map(|vertex| vertex.xyz() / vertex.w)
-            .fold(
-                ((MAX, MAX, MAX), (MIN, MIN, MIN)),
-                |((minx, miny, minz), (maxx, maxy, maxz)), vertex| {
-                    (
-                        (minx.min(vertex.x), miny.min(vertex.y), minz.min(vertex.z)),
-                        (maxx.max(vertex.x), maxy.max(vertex.y), maxz.max(vertex.z)),
-                    )
-                },
-            );
-            let min = na::Vector3::new(min.0, min.1, min.2);
-            let max = na::Vector3::new(max.0, max.1, max.2);
-            let new = ncollide3d::bounding_volume::AABB::from_half_extents(
-                na::Point3::from((max + min) / 2.0),
-                (max - min) / 2.0,
-            );
-            *aabb.entry(entity_id).or_insert(
-                ncollide3d::bounding_volume::AABB::from_half_extents(
-                    na::Point3::origin(),
-                    na::zero(),
-                ),
-            ) = new;
-        }
+        let mask = (entities, model_matrices, meshes).join_mask().into_owned();
+        aabb.replace_mask_with(&mask, || {
+            ncollide3d::bounding_volume::AABB::from_half_extents(na::Point3::origin(), na::zero())
+        });
+        let mut changed = croaring::Bitmap::fast_or(&[
+            model_matrices.modified(),
+            meshes.modified(),
+            aabb.modified(),
+        ]);
+        changed.and_inplace(&mask);
+        par_join((&changed, model_matrices, meshes, aabb)).for_each(
+            |(_, model_matrix, mesh, entity_aabb)| {
+                *entity_aabb = transform_aabb(&mesh.aabb, model_matrix);
+            },
+        );
     }
 }
 
+/// Bounds all 8 transformed corners, so it stays conservative under rotation and
+/// non-uniform scale
+pub fn transform_aabb(
+    aabb: &ncollide3d::bounding_volume::AABB<f32>,
+    model_matrix: &glm::Mat4,
+) -> ncollide3d::bounding_volume::AABB<f32> {
+    use std::f32::{MAX, MIN};
+    let min = aabb.mins();
+    let max = aabb.maxs();
+    let (min, max) = [
+        // bottom half (min y)
+        na::Point3::new(min.x, min.y, min.z),
+        na::Point3::new(max.x, min.y, min.z),
+        na::Point3::new(min.x, min.y, max.z),
+        na::Point3::new(max.x, min.y, max.z),
+        // top half (max y)
+        na::Point3::new(min.x, max.y, min.z),
+        na::Point3::new(max.x, max.y, min.z),
+        na::Point3::new(min.x, max.y, max.z),
+        na::Point3::new(max.x, max.y, max.z),
+    ]
+    .iter()
+    .map(|vertex| model_matrix * vertex.to_homogeneous())
+    .map(|vertex| vertex.xyz() / vertex.w)
+    .fold(
+        ((MAX, MAX, MAX), (MIN, MIN, MIN)),
+        |((minx, miny, minz), (maxx, maxy, maxz)), vertex| {
+            (
+                (minx.min(vertex.x), miny.min(vertex.y), minz.min(vertex.z)),
+                (maxx.max(vertex.x), maxy.max(vertex.y), maxz.max(vertex.z)),
+            )
+        },
+    );
+    let min = na::Vector3::new(min.0, min.1, min.2);
+    let max = na::Vector3::new(max.0, max.1, max.2);
+    ncollide3d::bounding_volume::AABB::from_half_extents(
+        na::Point3::from((max + min) / 2.0),
+        (max - min) / 2.0,
+    )
+}
+
+/// Input received during the frame. Systems only ever read input from here, so that it can be
+/// recorded and replayed, see `InputRecording`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct InputState {
-    key_presses: Vec<Option<VirtualKeyCode>>,
-    key_releases: Vec<Option<VirtualKeyCode>>,
-    button_presses: Vec<ButtonId>,
+    pub key_presses: Vec<Option<VirtualKeyCode>>,
+    pub key_releases: Vec<Option<VirtualKeyCode>>,
+    pub button_presses: Vec<ButtonId>,
+    pub button_releases: Vec<ButtonId>,
+    /// Summed raw mouse motion
+    pub mouse_delta: (f32, f32),
+    /// In physical pixels from the top left corner of the window, kept between frames
+    pub cursor_position: Option<(f32, f32)>,
 }
 
 impl InputState {
-    fn clear(&mut self) {
+    pub fn clear(&mut self) {
         self.key_presses.clear();
         self.key_releases.clear();
         self.button_presses.clear();
+        self.button_releases.clear();
+        self.mouse_delta = (0.0, 0.0);
     }
 }
 
@@ -325,31 +477,21 @@ impl Default for InputState {
             key_presses: vec![],
             key_releases: vec![],
             button_presses: vec![],
+            button_releases: vec![],
+            mouse_delta: (0.0, 0.0),
+            cursor_position: None,
         }
     }
 }
 
 pub struct FlyCamera {
-    forward: bool,
-    backward: bool,
-    up: bool,
-    down: bool,
-    right: bool,
-    left: bool,
-    fast: bool,
+    // previous and current simulated position, the camera is placed in between
+    simulated: Option<(na::Point3<f32>, na::Point3<f32>)>,
 }
 
 impl Default for FlyCamera {
     fn default() -> FlyCamera {
-        FlyCamera {
-            forward: false,
-            backward: false,
-            up: false,
-            down: false,
-            right: false,
-            left: false,
-            fast: false,
-        }
+        FlyCamera { simulated: None }
     }
 }
 
@@ -357,175 +499,728 @@ impl FlyCamera {
     pub fn exec(
         &mut self,
         input: &InputState,
+        actions: &ActionState,
         frame_timing: &FrameTiming,
         runtime_config: &RuntimeConfiguration,
         camera: &mut Camera,
     ) {
-        if !runtime_config.fly_mode {
+        if !runtime_config.fly_mode || runtime_config.camera_controller != CameraController::Fly {
+            self.simulated = None;
             return;
         }
 
-        for key in &input.key_presses {
-            match key {
-                Some(VirtualKeyCode::W) => {
-                    self.forward = true;
-                }
-                Some(VirtualKeyCode::S) => {
-                    self.backward = true;
-                }
-                Some(VirtualKeyCode::D) => {
-                    self.right = true;
-                }
-                Some(VirtualKeyCode::A) => {
-                    self.left = true;
-                }
-                Some(VirtualKeyCode::Space) => {
-                    self.up = true;
-                }
-                Some(VirtualKeyCode::LControl) => {
-                    self.down = true;
-                }
-                Some(VirtualKeyCode::LShift) => {
-                    self.fast = true;
-                }
-                _ => (),
-            }
-        }
-        for key in &input.key_releases {
-            match key {
-                Some(VirtualKeyCode::W) => {
-                    self.forward = false;
-                }
-                Some(VirtualKeyCode::S) => {
-                    self.backward = false;
-                }
-                Some(VirtualKeyCode::D) => {
-                    self.right = false;
-                }
-                Some(VirtualKeyCode::A) => {
-                    self.left = false;
-                }
-                Some(VirtualKeyCode::Space) => {
-                    self.up = false;
-                }
-                Some(VirtualKeyCode::LControl) => {
-                    self.down = false;
-                }
-                Some(VirtualKeyCode::LShift) => {
-                    self.fast = false;
-                }
-                _ => (),
-            }
-        }
-        let mut speed = if self.fast { 10.0 } else { 1.0 };
-        speed *= frame_timing.time_delta;
+        let (x, y) = input.mouse_delta;
+        let y_angle = f32::pi() / 180.0 * y;
+        let x_angle = f32::pi() / 180.0 * x;
+        camera.rotation *= na::Rotation3::from_axis_angle(&right_vector(), y_angle);
+        camera.rotation = na::Rotation3::from_axis_angle(&up_vector(), x_angle) * camera.rotation;
+
+        let mut speed = if actions.held(Action::MoveFast) {
+            10.0
+        } else {
+            1.0
+        };
+        speed *= FIXED_TIME_STEP;
         let mut increment: na::Vector3<f32> = na::zero();
-        if self.forward {
+        if actions.held(Action::MoveForward) {
             increment += speed * camera.rotation.transform_vector(&forward_vector())
         }
-        if self.backward {
+        if actions.held(Action::MoveBackward) {
             increment -= speed * camera.rotation.transform_vector(&forward_vector());
         }
-        if self.up {
+        if actions.held(Action::MoveUp) {
             increment += speed * camera.rotation.transform_vector(&up_vector());
         }
-        if self.down {
+        if actions.held(Action::MoveDown) {
             increment -= speed * camera.rotation.transform_vector(&up_vector());
         }
-        if self.right {
+        if actions.held(Action::MoveRight) {
             increment += speed * camera.rotation.transform_vector(&right_vector());
         }
-        if self.left {
+        if actions.held(Action::MoveLeft) {
             increment -= speed * camera.rotation.transform_vector(&right_vector());
         }
 
-        camera.position += increment;
+        let (mut previous, mut current) =
+            self.simulated.unwrap_or((camera.position, camera.position));
+        for _ in 0..frame_timing.steps() {
+            previous = current;
+            current += increment;
+        }
+        self.simulated = Some((previous, current));
+        camera.position = previous + (current - previous) * frame_timing.alpha();
+    }
+}
+
+/// Distance to the focus point when orbiting without a selected entity
+const DEFAULT_ORBIT_DISTANCE: f32 = 5.0;
+const MIN_ORBIT_DISTANCE: f32 = 0.1;
+
+/// Which controller writes the `Camera`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraController {
+    Fly,
+    /// Turntable around the focus point
+    Orbit {
+        focus: na::Point3<f32>,
+    },
+    /// Trails behind the entity, switches back to `Fly` once it's gone
+    Follow {
+        target: Entity,
+    },
+}
+
+impl CameraController {
+    /// Orbits around the position of `selected`, or the point in front of the camera
+    pub fn orbit(
+        camera: &Camera,
+        positions: &ComponentStorage<na::Point3<f32>>,
+        selected: Option<Entity>,
+    ) -> CameraController {
+        let focus = match selected {
+            Some(entity) if positions.mask().contains(entity.index) => {
+                *positions.get(entity).unwrap()
+            }
+            _ => {
+                camera.position
+                    + DEFAULT_ORBIT_DISTANCE * (camera.rotation * forward_vector().into_inner())
+            }
+        };
+        CameraController::Orbit { focus }
+    }
+
+    /// Follows `selected` if it's alive and has a position, otherwise stays in fly mode
+    pub fn follow(
+        entities: &EntitiesStorage,
+        positions: &ComponentStorage<na::Point3<f32>>,
+        selected: Option<Entity>,
+    ) -> CameraController {
+        match selected {
+            Some(target)
+                if entities.is_alive(target) && positions.mask().contains(target.index) =>
+            {
+                CameraController::Follow { target }
+            }
+            _ => CameraController::Fly,
+        }
+    }
+}
+
+/// Toggles the camera input and cycles through the controllers, orbit and follow use the
+/// `Selected` entity
+pub struct SelectCameraController;
+
+impl SelectCameraController {
+    pub fn exec(
+        actions: &ActionState,
+        selected: Option<Entity>,
+        entities: &EntitiesStorage,
+        positions: &ComponentStorage<na::Point3<f32>>,
+        camera: &Camera,
+        runtime_config: &mut RuntimeConfiguration,
+    ) {
+        if actions.pressed(Action::ToggleFly) {
+            runtime_config.fly_mode = !runtime_config.fly_mode;
+        }
+        if actions.pressed(Action::CycleCamera) {
+            runtime_config.camera_controller = match runtime_config.camera_controller {
+                CameraController::Fly => CameraController::orbit(camera, positions, selected),
+                CameraController::Orbit { .. } => {
+                    CameraController::follow(entities, positions, selected)
+                }
+                CameraController::Follow { .. } => CameraController::Fly,
+            };
+        }
+    }
+}
+
+/// Keeps the camera on a sphere around the focus point, looking at it. Mouse motion and the
+/// movement actions orbit, forward and backward zoom.
+pub struct OrbitCamera {
+    // focus the angles and distance were derived for
+    focus: Option<na::Point3<f32>>,
+    distance: f32,
+    yaw: f32,
+    pitch: f32,
+}
+
+impl Default for OrbitCamera {
+    fn default() -> OrbitCamera {
+        OrbitCamera {
+            focus: None,
+            distance: DEFAULT_ORBIT_DISTANCE,
+            yaw: 0.0,
+            pitch: 0.0,
+        }
+    }
+}
+
+impl OrbitCamera {
+    pub fn exec(
+        &mut self,
+        input: &InputState,
+        actions: &ActionState,
+        frame_timing: &FrameTiming,
+        runtime_config: &RuntimeConfiguration,
+        camera: &mut Camera,
+    ) {
+        let focus = match runtime_config.camera_controller {
+            CameraController::Orbit { focus } => focus,
+            _ => {
+                self.focus = None;
+                return;
+            }
+        };
+        if self.focus != Some(focus) {
+            // start from wherever the camera is, so that switching doesn't jump
+            let offset = focus - camera.position;
+            self.distance = offset.norm().max(MIN_ORBIT_DISTANCE);
+            let direction = if offset.norm() < MIN_ORBIT_DISTANCE {
+                camera.rotation * forward_vector().into_inner()
+            } else {
+                offset.normalize()
+            };
+            self.yaw = direction.x.atan2(direction.z);
+            self.pitch = (-direction.y).asin();
+            self.focus = Some(focus);
+        }
+
+        if runtime_config.fly_mode {
+            let (x, y) = input.mouse_delta;
+            self.yaw += f32::pi() / 180.0 * x;
+            self.pitch += f32::pi() / 180.0 * y;
+
+            let speed = if actions.held(Action::MoveFast) {
+                4.0
+            } else {
+                1.0
+            };
+            // radians per second, the zoom is exponential
+            let step = speed * frame_timing.time_delta();
+            if actions.held(Action::MoveRight) {
+                self.yaw -= step;
+            }
+            if actions.held(Action::MoveLeft) {
+                self.yaw += step;
+            }
+            if actions.held(Action::MoveUp) {
+                self.pitch += step;
+            }
+            if actions.held(Action::MoveDown) {
+                self.pitch -= step;
+            }
+            if actions.held(Action::MoveForward) {
+                self.distance *= (-step).exp();
+            }
+            if actions.held(Action::MoveBackward) {
+                self.distance *= step.exp();
+            }
+        }
+        let max_pitch = f32::frac_pi_2() - 0.01;
+        self.pitch = self.pitch.max(-max_pitch).min(max_pitch);
+        self.distance = self.distance.max(MIN_ORBIT_DISTANCE);
+
+        camera.rotation = na::UnitQuaternion::from_axis_angle(&up_vector(), self.yaw)
+            * na::UnitQuaternion::from_axis_angle(&right_vector(), self.pitch);
+        camera.position = focus - self.distance * (camera.rotation * forward_vector().into_inner());
+    }
+}
+
+const FOLLOW_DISTANCE: f32 = 4.0;
+const FOLLOW_HEIGHT: f32 = 1.5;
+/// How quickly the camera catches up with the target, higher is tighter
+const FOLLOW_STIFFNESS: f32 = 5.0;
+
+/// Trails behind the target along its rotation, looking at it
+pub struct FollowCamera;
+
+impl FollowCamera {
+    pub fn exec(
+        entities: &EntitiesStorage,
+        positions: &ComponentStorage<na::Point3<f32>>,
+        rotations: &ComponentStorage<na::UnitQuaternion<f32>>,
+        frame_timing: &FrameTiming,
+        runtime_config: &mut RuntimeConfiguration,
+        camera: &mut Camera,
+    ) {
+        let target = match runtime_config.camera_controller {
+            CameraController::Follow { target } => target,
+            _ => return,
+        };
+        if !entities.is_alive(target) || !positions.mask().contains(target.index) {
+            runtime_config.camera_controller = CameraController::Fly;
+            return;
+        }
+        let target_position = *positions.get(target).unwrap();
+        let target_rotation = if rotations.mask().contains(target.index) {
+            *rotations.get(target).unwrap()
+        } else {
+            na::UnitQuaternion::identity()
+        };
+        let desired = target_position
+            - FOLLOW_DISTANCE * (target_rotation * forward_vector().into_inner())
+            + FOLLOW_HEIGHT * up_vector().into_inner();
+        let blend = 1.0 - (-FOLLOW_STIFFNESS * frame_timing.time_delta()).exp();
+        camera.position += (desired - camera.position) * blend;
+
+        let look = target_position - camera.position;
+        // facing straight up or down has no defined rotation around the view axis
+        if look.cross(&up_vector()).norm() > 1e-4 {
+            camera.rotation = na::UnitQuaternion::face_towards(&look, &up_vector());
+        }
     }
 }
 
+/// Fires from the camera in fly mode
 pub struct LaunchProjectileTest;
 
 impl LaunchProjectileTest {
-    #[allow(clippy::too_many_arguments)]
     pub fn exec(
-        entities: &mut EntitiesStorage,
-        position_storage: &mut ComponentStorage<na::Point3<f32>>,
-        rotation_storage: &mut ComponentStorage<na::UnitQuaternion<f32>>,
-        scale_storage: &mut ComponentStorage<f32>,
-        meshes_storage: &mut ComponentStorage<GltfMesh>,
-        textures_storage: &mut ComponentStorage<GltfMeshBaseColorTexture>,
-        projectile_target_storage: &mut ComponentStorage<na::Point3<f32>>,
-        projectile_velocities_storage: &mut ComponentStorage<f32>,
-        camera: &mut Camera,
-        mesh_library: &MeshLibrary,
-        input_state: &InputState,
+        camera: &Camera,
+        prefabs: &Prefabs,
+        actions: &ActionState,
+        runtime_config: &RuntimeConfiguration,
+        commands: &EntityCommands,
     ) {
-        if input_state.button_presses.iter().any(|p| *p == 1) {
-            let projectile = entities.allocate();
-            position_storage.insert(projectile, camera.position);
-            rotation_storage.insert(projectile, camera.rotation);
-            scale_storage.insert(projectile, 1.0);
+        if runtime_config.fly_mode && actions.pressed(Action::Fire) {
             let target =
                 camera.position + camera.rotation * (100.0 * (&forward_vector().into_inner()));
-            projectile_target_storage.insert(projectile, target);
-            projectile_velocities_storage.insert(projectile, 20.0);
-            meshes_storage.insert(projectile, mesh_library.projectile.clone());
-            textures_storage.insert(
-                projectile,
-                GltfMeshBaseColorTexture(Arc::clone(&mesh_library.projectile_texture)),
+            prefabs.spawn_deferred(
+                commands,
+                "projectile",
+                Prefab {
+                    position: Some(camera.position),
+                    rotation: Some(camera.rotation),
+                    projectile_target: Some(target),
+                    ..Prefab::default()
+                },
             );
         }
     }
 }
 
+/// Advances projectiles by one simulation step
 pub struct UpdateProjectiles;
 
 impl UpdateProjectiles {
     pub fn exec(
-        entities: &mut EntitiesStorage,
+        entities: &EntitiesStorage,
         position_storage: &mut ComponentStorage<na::Point3<f32>>,
         rotation_storage: &ComponentStorage<na::UnitQuaternion<f32>>,
-        projectile_target_storage: &ComponentStorage<na::Point3<f32>>,
-        projectile_velocities_storage: &mut ComponentStorage<f32>,
-        frame_timing: &FrameTiming,
+        projectile_target_storage: &ComponentStorage<ProjectileTarget>,
+        projectile_velocities_storage: &ComponentStorage<ProjectileVelocity>,
+        previous_transforms: &mut ComponentStorage<PreviousTransform>,
+        commands: &EntityCommands,
     ) {
-        let projectiles = entities.mask()
-            & rotation_storage.mask()
-            & projectile_target_storage.mask()
-            & projectile_velocities_storage.mask();
-        for projectile in projectiles.iter() {
-            let position = position_storage.entry(projectile).assume();
-            let target = projectile_target_storage.get(projectile).unwrap();
-            if na::distance(position, target) < 0.1 {
-                entities.remove(projectile);
+        for (projectile, position, rotation, target, velocity) in join((
+            entities,
+            position_storage,
+            rotation_storage,
+            projectile_target_storage,
+            projectile_velocities_storage,
+        )) {
+            let previous =
+                previous_transforms
+                    .entry(projectile)
+                    .or_insert_with(|| PreviousTransform {
+                        position: *position,
+                        rotation: *rotation,
+                    });
+            previous.position = *position;
+            previous.rotation = *rotation;
+
+            let step = velocity.0 * FIXED_TIME_STEP;
+            // arrives within this step, snap to the target instead of overshooting it
+            if na::distance(position, &target.0) <= step {
+                *position = target.0;
+                commands.despawn(entities.entity(projectile));
                 continue;
             }
-            let rotation = rotation_storage.get(projectile).unwrap();
-            let velocity = projectile_velocities_storage.get(projectile).unwrap();
-            let velocity_scaled = velocity * frame_timing.time_delta;
-            let increment = velocity_scaled * (rotation * forward_vector().into_inner());
-            *position += increment;
+            *position += step * (rotation * forward_vector().into_inner());
         }
     }
 }
 
+/// Projectile impact, the normal faces the side the projectile came from
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProjectileHit {
+    pub projectile: Entity,
+    pub entity: Entity,
+    pub point: na::Point3<f32>,
+    pub normal: na::Unit<na::Vector3<f32>>,
+}
+
+/// Hits from all the simulation steps of the frame, cleared at the start of every frame
+#[derive(Default)]
+pub struct ProjectileHits(pub Vec<ProjectileHit>);
+
+/// Sweeps the segment each projectile moved along in the last simulation step against the world
+/// AABBs of everything else, found through the `SpatialIndex`. With `precise_collisions` the
+/// AABBs only narrow down the candidates and the hit is found on the mesh triangles instead.
+/// Projectiles stop at the closest hit and are despawned at the end of the frame, they are
+/// skipped in the remaining steps until then.
+pub struct ProjectileCollision;
+
+impl ProjectileCollision {
+    #[allow(clippy::too_many_arguments)]
+    pub fn exec(
+        entities: &EntitiesStorage,
+        position_storage: &mut ComponentStorage<na::Point3<f32>>,
+        projectile_velocities_storage: &ComponentStorage<ProjectileVelocity>,
+        previous_transforms: &ComponentStorage<PreviousTransform>,
+        aabb_storage: &ComponentStorage<ncollide3d::bounding_volume::AABB<f32>>,
+        spatial_index: &SpatialIndex,
+        model_matrices: &ComponentStorage<glm::Mat4>,
+        meshes: &ComponentStorage<GltfMesh>,
+        runtime_config: &RuntimeConfiguration,
+        hits: &mut ProjectileHits,
+        commands: &EntityCommands,
+    ) {
+        let mut targets = (entities, aabb_storage).join_mask().into_owned();
+        targets.andnot_inplace(projectile_velocities_storage.mask());
+        // hit in an earlier step of the frame, still waiting for the despawn
+        let mut projectiles = projectile_velocities_storage.mask().clone();
+        for hit in hits.0.iter() {
+            projectiles.remove(hit.projectile.index);
+        }
+        for (projectile, _, position, previous) in join((
+            entities,
+            &projectiles,
+            &mut *position_storage,
+            previous_transforms,
+        )) {
+            let ray = ncollide3d::query::Ray::new(previous.position, *position - previous.position);
+            if ray.dir == na::zero() {
+                continue;
+            }
+            let closest = closest_hit(
+                &ray,
+                runtime_config.precise_collisions,
+                &targets,
+                entities,
+                spatial_index,
+                aabb_storage,
+                model_matrices,
+                meshes,
+            );
+            if let Some((target, hit)) = closest {
+                *position = ray.point_at(hit.toi);
+                // face the normal against the ray, also when it started inside of an AABB
+                let normal = na::Unit::try_new(hit.normal, 1.0e-6)
+                    .unwrap_or_else(|| -na::Unit::new_normalize(ray.dir));
+                let normal = if normal.dot(&ray.dir) > 0.0 {
+                    -normal
+                } else {
+                    normal
+                };
+                let projectile = entities.entity(projectile);
+                hits.0.push(ProjectileHit {
+                    projectile,
+                    entity: target,
+                    point: *position,
+                    normal,
+                });
+                commands.despawn(projectile);
+            }
+        }
+    }
+}
+
+/// Closest of the `targets` along the ray, see `sweep()`. The `SpatialIndex` only catches up
+/// later in the frame, so the entities it returns are checked against the storages.
+#[allow(clippy::too_many_arguments)]
+fn closest_hit(
+    ray: &ncollide3d::query::Ray<f32>,
+    precise: bool,
+    targets: &croaring::Bitmap,
+    entities: &EntitiesStorage,
+    spatial_index: &SpatialIndex,
+    aabbs: &ComponentStorage<ncollide3d::bounding_volume::AABB<f32>>,
+    model_matrices: &ComponentStorage<glm::Mat4>,
+    meshes: &ComponentStorage<GltfMesh>,
+) -> Option<(Entity, ncollide3d::query::RayIntersection<f32>)> {
+    let mut closest: Option<(Entity, ncollide3d::query::RayIntersection<f32>)> = None;
+    for (entity, aabb_toi) in spatial_index.ray(ray, 1.0) {
+        // sorted by the AABB hits, which come before any hit on the triangles inside
+        if closest.map_or(false, |(_, hit)| hit.toi < aabb_toi) {
+            break;
+        }
+        if !entities.is_alive(entity)
+            || !targets.contains(entity.index)
+            || !aabbs.mask().contains(entity.index)
+        {
+            continue;
+        }
+        let collision_mesh = if precise {
+            collision_mesh(entity.index, model_matrices, meshes)
+        } else {
+            None
+        };
+        if let Some(hit) = sweep(ray, aabbs.get(entity).unwrap(), collision_mesh) {
+            if closest.map_or(true, |(_, closest)| hit.toi < closest.toi) {
+                closest = Some((entity, hit));
+            }
+        }
+    }
+    closest
+}
+
+/// Model matrix and triangles of the entity's mesh, if it has both
+fn collision_mesh<'a>(
+    entity_id: u32,
+    model_matrices: &'a ComponentStorage<glm::Mat4>,
+    meshes: &'a ComponentStorage<GltfMesh>,
+) -> Option<(&'a glm::Mat4, &'a ncollide3d::shape::TriMesh<f32>)> {
+    if model_matrices.mask().contains(entity_id) && meshes.mask().contains(entity_id) {
+        Some((
+            model_matrices.get(entity_id).unwrap(),
+            &*meshes.get(entity_id).unwrap().collision_mesh,
+        ))
+    } else {
+        None
+    }
+}
+
+/// Intersection of the ray with `aabb`, or with the triangles of a model space collision mesh
+/// inside of it, if given. Only hits within the ray's length count, `toi` is a fraction of it.
+fn sweep(
+    ray: &ncollide3d::query::Ray<f32>,
+    aabb: &ncollide3d::bounding_volume::AABB<f32>,
+    collision_mesh: Option<(&glm::Mat4, &ncollide3d::shape::TriMesh<f32>)>,
+) -> Option<ncollide3d::query::RayIntersection<f32>> {
+    use ncollide3d::query::{Ray, RayCast};
+
+    let identity = na::Isometry3::identity();
+    let hit = aabb
+        .toi_and_normal_with_ray(&identity, ray, true)
+        .filter(|hit| hit.toi <= 1.0)?;
+    let (model_matrix, collision_mesh) = match collision_mesh {
+        Some(collision_mesh) => collision_mesh,
+        None => return Some(hit),
+    };
+    // the time of impact is preserved when the direction is transformed without normalizing
+    let inverse = model_matrix.try_inverse()?;
+    let local_ray = Ray::new(
+        inverse.transform_point(&ray.origin),
+        inverse.transform_vector(&ray.dir),
+    );
+    let mut hit = collision_mesh
+        .toi_and_normal_with_ray(&identity, &local_ray, false)
+        .filter(|hit| hit.toi <= 1.0)?;
+    hit.normal = inverse.transpose().transform_vector(&hit.normal);
+    Some(hit)
+}
+
+/// Entity picked with the mouse or in the inspector
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Selected {
+    pub entity: Option<Entity>,
+    /// Where the cursor ray hit the entity, if it was picked with the mouse
+    pub point: Option<na::Point3<f32>>,
+}
+
+/// How far from the camera entities can be picked
+const PICK_DISTANCE: f32 = 1000.0;
+
+/// Selects the entity under the cursor on `Action::Select` while the cursor is free, clicking
+/// on nothing clears the selection. Candidates come from the `SpatialIndex` and are refined
+/// against mesh triangles with `precise_collisions`, like projectile hits. Needs to run after
+/// `ManipulateGizmo`, so that grabbing a handle doesn't change the selection.
+pub struct MousePicking;
+
+impl MousePicking {
+    #[allow(clippy::too_many_arguments)]
+    pub fn exec(
+        input: &InputState,
+        actions: &ActionState,
+        runtime_config: &RuntimeConfiguration,
+        swapchain: &Swapchain,
+        camera: &Camera,
+        entities: &EntitiesStorage,
+        spatial_index: &SpatialIndex,
+        aabbs: &ComponentStorage<ncollide3d::bounding_volume::AABB<f32>>,
+        model_matrices: &ComponentStorage<glm::Mat4>,
+        meshes: &ComponentStorage<GltfMesh>,
+        gizmo: &Gizmo,
+        selected: &mut Selected,
+    ) {
+        #[cfg(feature = "profiling")]
+        microprofile::scope!("ecs", "mouse picking");
+        // the click grabbed a gizmo handle
+        if runtime_config.fly_mode || !actions.pressed(Action::Select) || gizmo.dragging() {
+            return;
+        }
+        let cursor = match input.cursor_position {
+            Some(cursor) => cursor,
+            None => return,
+        };
+        let viewport = (swapchain.width as f32, swapchain.height as f32);
+        let ray = MousePicking::cursor_ray(camera, cursor, viewport);
+        let (entity, point) = MousePicking::pick(
+            &ray,
+            runtime_config.precise_collisions,
+            entities,
+            spatial_index,
+            aabbs,
+            model_matrices,
+            meshes,
+        )
+        .map_or((None, None), |(entity, point)| (Some(entity), Some(point)));
+        *selected = Selected { entity, point };
+    }
+
+    /// World space ray from the near plane through the cursor, `PICK_DISTANCE` long
+    pub fn cursor_ray(
+        camera: &Camera,
+        cursor: (f32, f32),
+        viewport: (f32, f32),
+    ) -> ncollide3d::query::Ray<f32> {
+        // the viewport is flipped, y points up in NDC
+        let x = 2.0 * cursor.0 / viewport.0 - 1.0;
+        let y = 1.0 - 2.0 * cursor.1 / viewport.1;
+        let inverse = (camera.projection * camera.view)
+            .try_inverse()
+            .expect("camera matrices are not invertible");
+        let unproject = |depth: f32| {
+            let point = inverse * na::Vector4::new(x, y, depth, 1.0);
+            na::Point3::from(point.xyz() / point.w)
+        };
+        // depth is reversed, 1 is on the near plane and 0 may be infinitely far away
+        let origin = unproject(1.0);
+        let dir = (unproject(0.5) - origin).normalize();
+        ncollide3d::query::Ray::new(origin, PICK_DISTANCE * dir)
+    }
+
+    /// Closest entity along the ray and the point where it was hit
+    fn pick(
+        ray: &ncollide3d::query::Ray<f32>,
+        precise: bool,
+        entities: &EntitiesStorage,
+        spatial_index: &SpatialIndex,
+        aabbs: &ComponentStorage<ncollide3d::bounding_volume::AABB<f32>>,
+        model_matrices: &ComponentStorage<glm::Mat4>,
+        meshes: &ComponentStorage<GltfMesh>,
+    ) -> Option<(Entity, na::Point3<f32>)> {
+        closest_hit(
+            ray,
+            precise,
+            aabbs.mask(),
+            entities,
+            spatial_index,
+            aabbs,
+            model_matrices,
+            meshes,
+        )
+        .map(|(entity, hit)| (entity, ray.point_at(hit.toi)))
+    }
+}
+
 /// Grab-bag for renderer and player controller variables for now
 pub struct RuntimeConfiguration {
     pub debug_aabbs: bool,
+    /// Whether projectile hits and mouse picking are refined against mesh triangles or only
+    /// use AABBs
+    pub precise_collisions: bool,
+    /// Whether the camera controller takes input
     pub fly_mode: bool,
+    pub camera_controller: CameraController,
+    /// Requests a world snapshot to be saved at the end of the frame
+    pub save_snapshot: bool,
+    /// Requests the saved world snapshot to be restored at the end of the frame
+    pub load_snapshot: bool,
+    /// Requests the camera projection to change at the start of the next frame
+    pub projection_mode: Option<ProjectionMode>,
 }
 
 impl RuntimeConfiguration {
     pub fn new() -> RuntimeConfiguration {
         RuntimeConfiguration {
             debug_aabbs: false,
+            precise_collisions: true,
             fly_mode: false,
+            camera_controller: CameraController::Fly,
+            save_snapshot: false,
+            load_snapshot: false,
+            projection_mode: None,
+        }
+    }
+}
+
+/// Returns whether the projection was changed, values are kept in a valid range
+fn edit_projection_mode(ui: &imgui::Ui, mode: &mut ProjectionMode) -> bool {
+    let (fov, near) = match *mode {
+        ProjectionMode::Perspective { fov_y, near, .. } => (fov_y, near),
+        ProjectionMode::InfinitePerspective { fov_y, near } => (fov_y, near),
+        ProjectionMode::Orthographic { near, .. } => (70.0, near),
+    };
+    let (perspective, infinite, orthographic) = match mode {
+        ProjectionMode::Perspective { .. } => (true, false, false),
+        ProjectionMode::InfinitePerspective { .. } => (false, true, false),
+        ProjectionMode::Orthographic { .. } => (false, false, true),
+    };
+    let mut changed = false;
+    ui.text(&im_str!("Projection:"));
+    if ui.radio_button_bool(&im_str!("Perspective"), perspective) {
+        *mode = ProjectionMode::Perspective {
+            fov_y: fov,
+            near,
+            far: 1000.0,
+        };
+        changed = true;
+    }
+    ui.same_line(0.0);
+    if ui.radio_button_bool(&im_str!("Infinite"), infinite) {
+        *mode = ProjectionMode::InfinitePerspective { fov_y: fov, near };
+        changed = true;
+    }
+    ui.same_line(0.0);
+    if ui.radio_button_bool(&im_str!("Orthographic"), orthographic) {
+        *mode = ProjectionMode::Orthographic {
+            height: 10.0,
+            near,
+            far: 1000.0,
+        };
+        changed = true;
+    }
+    match mode {
+        ProjectionMode::Perspective { fov_y, near, far } => {
+            changed |= ui.input_float(&im_str!("fov y"), fov_y).build();
+            changed |= ui.input_float(&im_str!("near"), near).build();
+            changed |= ui.input_float(&im_str!("far"), far).build();
+        }
+        ProjectionMode::InfinitePerspective { fov_y, near } => {
+            changed |= ui.input_float(&im_str!("fov y"), fov_y).build();
+            changed |= ui.input_float(&im_str!("near"), near).build();
+        }
+        ProjectionMode::Orthographic { height, near, far } => {
+            changed |= ui.input_float(&im_str!("height"), height).build();
+            changed |= ui.input_float(&im_str!("near"), near).build();
+            changed |= ui.input_float(&im_str!("far"), far).build();
+        }
+    }
+    match mode {
+        ProjectionMode::Perspective { fov_y, near, far } => {
+            *fov_y = fov_y.max(1.0).min(179.0);
+            *near = near.max(0.001);
+            *far = far.max(*near * 2.0);
+        }
+        ProjectionMode::InfinitePerspective { fov_y, near } => {
+            *fov_y = fov_y.max(1.0).min(179.0);
+            *near = near.max(0.001);
+        }
+        ProjectionMode::Orthographic { height, near, far } => {
+            *height = height.max(0.01);
+            *far = far.max(*near + 0.01);
         }
     }
+    changed
 }
 
 pub struct Gui {
     pub imgui: imgui::Context,
+    pub inspector: Inspector,
+    pub key_bindings_editor: KeyBindingsEditor,
+    last_projectile_hit: Option<ProjectileHit>,
 }
 
 impl Gui {
@@ -535,7 +1230,12 @@ impl Gui {
         imgui.style_mut().frame_border_size = 1.0;
         imgui.style_mut().frame_rounding = 4.0;
 
-        Gui { imgui }
+        Gui {
+            imgui,
+            inspector: Inspector::new(),
+            key_bindings_editor: KeyBindingsEditor::new(),
+            last_projectile_hit: None,
+        }
     }
 
     pub fn update<'a>(
@@ -545,8 +1245,14 @@ impl Gui {
         swapchain: &Swapchain,
         camera: &Camera,
         runtime_config: &mut RuntimeConfiguration,
+        world: &World,
     ) -> &'a imgui::DrawData {
-        let imgui = &mut self.imgui;
+        let Gui {
+            imgui,
+            inspector,
+            key_bindings_editor,
+            last_projectile_hit,
+        } = self;
         imgui.io_mut().display_size = [swapchain.width as f32, swapchain.height as f32];
         input_handler
             .imgui_platform
@@ -590,16 +1296,83 @@ impl Gui {
                     );
                     let s = format!("rotation: x={:5.2} y={:5.2} z={:5.2}", x, y, z);
                     ui.bullet_text(&im_str!("{}", s));
+                    let (toggle_fly, cycle_camera) = {
+                        let key_bindings = world.read::<KeyBindings>();
+                        (
+                            key_bindings.describe(Action::ToggleFly),
+                            key_bindings.describe(Action::CycleCamera),
+                        )
+                    };
                     ui.checkbox(
-                        &im_str!("[G] Camera fly mode"),
+                        &im_str!("[{}] Camera fly mode", toggle_fly),
                         &mut runtime_config.fly_mode,
                     );
+                    ui.text(&im_str!("[{}] Camera controller:", cycle_camera));
+                    let (orbiting, following) = match runtime_config.camera_controller {
+                        CameraController::Fly => (false, false),
+                        CameraController::Orbit { .. } => (true, false),
+                        CameraController::Follow { .. } => (false, true),
+                    };
+                    if ui.radio_button_bool(&im_str!("Fly"), !orbiting && !following) {
+                        runtime_config.camera_controller = CameraController::Fly;
+                    }
+                    ui.same_line(0.0);
+                    if ui.radio_button_bool(&im_str!("Orbit selected"), orbiting) {
+                        runtime_config.camera_controller = CameraController::orbit(
+                            camera,
+                            &world.storage(),
+                            world.read::<Selected>().entity,
+                        );
+                    }
+                    ui.same_line(0.0);
+                    if ui.radio_button_bool(&im_str!("Follow selected"), following) {
+                        runtime_config.camera_controller = CameraController::follow(
+                            &world.entities(),
+                            &world.storage(),
+                            world.read::<Selected>().entity,
+                        );
+                    }
+                    let mut projection_mode = camera.projection_mode;
+                    if edit_projection_mode(&ui, &mut projection_mode) {
+                        runtime_config.projection_mode = Some(projection_mode);
+                    }
                     ui.spacing();
                 }
                 ui.checkbox(
                     &im_str!("Debug collision AABBs"),
                     &mut runtime_config.debug_aabbs,
                 );
+                ui.checkbox(
+                    &im_str!("Collide and pick against mesh triangles"),
+                    &mut runtime_config.precise_collisions,
+                );
+                ui.spacing();
+                world.write::<Gizmo>().draw(&ui);
+                ui.spacing();
+                if let Some(hit) = world.read::<ProjectileHits>().0.last() {
+                    *last_projectile_hit = Some(hit.clone());
+                }
+                if let Some(ref hit) = last_projectile_hit {
+                    ui.text(&im_str!("Last projectile hit: {:?}", hit.entity));
+                    let (point, normal) = (hit.point, hit.normal);
+                    let s = format!("point: x={:.2} y={:.2} z={:.2}", point.x, point.y, point.z);
+                    ui.bullet_text(&im_str!("{}", s));
+                    let s = format!(
+                        "normal: x={:.2} y={:.2} z={:.2}",
+                        normal.x, normal.y, normal.z
+                    );
+                    ui.bullet_text(&im_str!("{}", s));
+                }
+                if ui.button(&im_str!("Save snapshot"), [0.0, 0.0]) {
+                    runtime_config.save_snapshot = true;
+                }
+                ui.same_line(0.0);
+                if ui.button(&im_str!("Load snapshot"), [0.0, 0.0]) {
+                    runtime_config.load_snapshot = true;
+                }
+                ui.spacing();
+                inspector.draw(&ui, world);
+                key_bindings_editor.draw(&ui, world);
             });
 
         input_handler
@@ -609,3 +1382,400 @@ impl Gui {
         ui.render()
     }
 }
+
+#[test]
+fn test_model_matrix_despawned_parent() {
+    let mut world = World::new();
+    world.register::<na::Point3<f32>>();
+    world.register::<na::UnitQuaternion<f32>>();
+    world.register::<Scale>();
+    world.register::<Parent>();
+    world.register::<PreviousTransform>();
+    world.register::<glm::Mat4>();
+    let (parent, child) = {
+        let mut entities = world.entities_mut();
+        (entities.allocate(), entities.allocate())
+    };
+    for entity in [parent, child].iter() {
+        world
+            .storage_mut::<na::Point3<f32>>()
+            .insert(*entity, na::Point3::new(1.0, 0.0, 0.0));
+        world
+            .storage_mut::<na::UnitQuaternion<f32>>()
+            .insert(*entity, na::UnitQuaternion::identity());
+        world
+            .storage_mut::<Scale>()
+            .insert(*entity, Scale(na::Vector3::repeat(1.0)));
+    }
+    world.storage_mut::<Parent>().insert(child, Parent(parent));
+    let calculate = || {
+        ModelMatrixCalculation::exec(
+            &world.entities(),
+            &FrameTiming::default(),
+            &world.storage::<na::Point3<f32>>(),
+            &world.storage::<na::UnitQuaternion<f32>>(),
+            &world.storage::<Scale>(),
+            &world.storage::<Parent>(),
+            &world.storage::<PreviousTransform>(),
+            &mut world.storage_mut::<glm::Mat4>(),
+        );
+        world.storage::<glm::Mat4>().get(child).unwrap()[(0, 3)]
+    };
+    assert_eq!(calculate(), 2.0);
+    world.maintain();
+    assert_eq!(calculate(), 2.0);
+
+    world.entities_mut().remove(parent);
+    world.maintain();
+    // the child keeps its dangling Parent but is placed as a root now
+    assert!(world.storage::<Parent>().mask().contains(child.index));
+    assert_eq!(calculate(), 1.0);
+}
+
+#[test]
+fn test_transform_aabb_scale() {
+    let aabb = ncollide3d::bounding_volume::AABB::new(
+        na::Point3::new(-1.0, -1.0, -1.0),
+        na::Point3::new(1.0, 1.0, 1.0),
+    );
+    let model_matrix =
+        glm::translation(&glm::vec3(10.0, 0.0, 0.0)) * glm::scaling(&glm::vec3(1.0, 2.0, 3.0));
+    let transformed = transform_aabb(&aabb, &model_matrix);
+    assert_eq!(*transformed.mins(), na::Point3::new(9.0, -2.0, -3.0));
+    assert_eq!(*transformed.maxs(), na::Point3::new(11.0, 2.0, 3.0));
+}
+
+#[test]
+fn test_transform_aabb_rotated_scale() {
+    let aabb = ncollide3d::bounding_volume::AABB::new(
+        na::Point3::new(0.0, 0.0, 0.0),
+        na::Point3::new(1.0, 1.0, 1.0),
+    );
+    // stretched along x, then turned so that x points down the z axis
+    let rotation = na::UnitQuaternion::from_axis_angle(&na::Vector3::y_axis(), -f32::frac_pi_2());
+    let model_matrix = rotation.to_homogeneous() * glm::scaling(&glm::vec3(4.0, 1.0, 1.0));
+    let transformed = transform_aabb(&aabb, &model_matrix);
+    let expected_mins = na::Point3::new(-1.0, 0.0, 0.0);
+    let expected_maxs = na::Point3::new(0.0, 1.0, 4.0);
+    assert!((transformed.mins() - expected_mins).norm() < 1e-5);
+    assert!((transformed.maxs() - expected_maxs).norm() < 1e-5);
+}
+
+#[test]
+fn test_frame_timing_advance() {
+    let mut frame_timing = FrameTiming::default();
+    frame_timing.advance(FIXED_TIME_STEP * 0.5);
+    assert_eq!(frame_timing.steps(), 0);
+    assert!((frame_timing.alpha() - 0.5).abs() < 1e-4);
+    // the leftover half step carries over
+    frame_timing.advance(FIXED_TIME_STEP * 2.0);
+    assert_eq!(frame_timing.steps(), 2);
+    assert!((frame_timing.alpha() - 0.5).abs() < 1e-4);
+    // a long stall doesn't try to catch up all at once
+    frame_timing.advance(1.0);
+    assert_eq!(frame_timing.steps(), MAX_STEPS_PER_FRAME);
+    assert!(frame_timing.alpha() < 1.0);
+}
+
+#[test]
+fn test_orbit_camera() {
+    let focus = na::Point3::new(0.0, 1.0, 7.0);
+    let mut runtime_config = RuntimeConfiguration::new();
+    runtime_config.camera_controller = CameraController::Orbit { focus };
+    let mut camera = Camera::default();
+    let mut orbit_camera = OrbitCamera::default();
+    let actions = ActionState::default();
+    let frame_timing = FrameTiming::default();
+    let mut input = InputState::default();
+    // picks up from the current camera, which is already looking at the focus
+    orbit_camera.exec(
+        &input,
+        &actions,
+        &frame_timing,
+        &runtime_config,
+        &mut camera,
+    );
+    assert!((camera.position - na::Point3::new(0.0, 1.0, 2.0)).norm() < 1e-5);
+    assert!(camera.rotation.angle() < 1e-5);
+
+    runtime_config.fly_mode = true;
+    input.mouse_delta = (90.0, 0.0);
+    orbit_camera.exec(
+        &input,
+        &actions,
+        &frame_timing,
+        &runtime_config,
+        &mut camera,
+    );
+    assert!((camera.position - na::Point3::new(-5.0, 1.0, 7.0)).norm() < 1e-5);
+    let forward = camera.rotation * forward_vector().into_inner();
+    assert!((forward - na::Vector3::x()).norm() < 1e-5);
+}
+
+#[test]
+fn test_follow_camera() {
+    let mut entities = EntitiesStorage::new();
+    let mut positions = ComponentStorage::new();
+    let rotations = ComponentStorage::new();
+    let target = entities.allocate();
+    positions.insert(target, na::Point3::origin());
+    let mut runtime_config = RuntimeConfiguration::new();
+    runtime_config.camera_controller = CameraController::Follow { target };
+    let mut camera = Camera::default();
+    let mut frame_timing = FrameTiming::default();
+    frame_timing.advance(10.0);
+    FollowCamera::exec(
+        &entities,
+        &positions,
+        &rotations,
+        &frame_timing,
+        &mut runtime_config,
+        &mut camera,
+    );
+    let expected = na::Point3::new(0.0, FOLLOW_HEIGHT, -FOLLOW_DISTANCE);
+    assert!((camera.position - expected).norm() < 1e-3);
+    let forward = camera.rotation * forward_vector().into_inner();
+    assert!((forward - (na::Point3::origin() - camera.position).normalize()).norm() < 1e-5);
+
+    entities.remove(target);
+    FollowCamera::exec(
+        &entities,
+        &positions,
+        &rotations,
+        &frame_timing,
+        &mut runtime_config,
+        &mut camera,
+    );
+    assert_eq!(runtime_config.camera_controller, CameraController::Fly);
+}
+
+#[test]
+fn test_projection_depth() {
+    let depth = |mode: ProjectionMode, z: f32| {
+        let clip = mode.matrix(1.5) * na::Vector4::new(0.0, 0.0, z, 1.0);
+        clip.z / clip.w
+    };
+    let perspective = ProjectionMode::Perspective {
+        fov_y: 70.0,
+        near: 0.1,
+        far: 100.0,
+    };
+    assert!((depth(perspective, 0.1) - 1.0).abs() < 1e-5);
+    assert!(depth(perspective, 100.0).abs() < 1e-5);
+    let infinite = ProjectionMode::InfinitePerspective {
+        fov_y: 70.0,
+        near: 0.1,
+    };
+    assert!((depth(infinite, 0.1) - 1.0).abs() < 1e-5);
+    assert!(depth(infinite, 1.0e6) > 0.0);
+    assert!(depth(infinite, 1.0e6) < 1.0e-6);
+    let orthographic = ProjectionMode::Orthographic {
+        height: 10.0,
+        near: 0.1,
+        far: 100.0,
+    };
+    assert!((depth(orthographic, 0.1) - 1.0).abs() < 1e-5);
+    assert!(depth(orthographic, 100.0).abs() < 1e-5);
+    // the vertical extent is fixed for orthographic, whatever the distance
+    let top = orthographic.matrix(1.5) * na::Vector4::new(0.0, 5.0, 50.0, 1.0);
+    assert!((top.y / top.w - 1.0).abs() < 1e-5);
+}
+
+#[test]
+fn test_frustum_planes() {
+    let outside = |camera: &Camera, point: na::Point3<f32>| {
+        camera
+            .frustum_planes
+            .iter()
+            .any(|plane| plane.dot(&point.to_homogeneous()) > 0.0)
+    };
+    let mut camera = Camera {
+        position: na::Point3::origin(),
+        ..Camera::default()
+    };
+    ProjectCamera::project(1.0, &mut camera);
+    assert!(!outside(&camera, na::Point3::new(0.0, 0.0, 1.0)));
+    assert!(!outside(&camera, na::Point3::new(0.0, 0.0, 1.0e5)));
+    assert!(outside(&camera, na::Point3::new(0.0, 0.0, 0.05)));
+    assert!(outside(&camera, na::Point3::new(0.0, 0.0, -1.0)));
+    assert!(outside(&camera, na::Point3::new(10.0, 0.0, 1.0)));
+
+    camera.projection_mode = ProjectionMode::Orthographic {
+        height: 10.0,
+        near: 0.1,
+        far: 100.0,
+    };
+    ProjectCamera::project(1.0, &mut camera);
+    assert!(!outside(&camera, na::Point3::new(4.0, -4.0, 50.0)));
+    assert!(outside(&camera, na::Point3::new(6.0, 0.0, 50.0)));
+    assert!(outside(&camera, na::Point3::new(0.0, 0.0, 150.0)));
+}
+
+#[test]
+fn test_projectile_collision() {
+    use ncollide3d::bounding_volume::AABB;
+
+    let mut world = World::new();
+    world.register::<na::Point3<f32>>();
+    world.register::<ProjectileVelocity>();
+    world.register::<PreviousTransform>();
+    world.register::<AABB<f32>>();
+    world.register::<glm::Mat4>();
+    world.register::<GltfMesh>();
+    let (near_wall, far_wall, projectile, missing) = {
+        let mut entities = world.entities_mut();
+        (
+            entities.allocate(),
+            entities.allocate(),
+            entities.allocate(),
+            entities.allocate(),
+        )
+    };
+    let wall = |z: f32| {
+        AABB::new(
+            na::Point3::new(-1.0, -1.0, z),
+            na::Point3::new(1.0, 1.0, z + 1.0),
+        )
+    };
+    let mut spatial_index = SpatialIndex::new();
+    // the projectile's own AABB is not a target
+    for &(entity, z) in [(near_wall, 2.0), (far_wall, 4.0), (projectile, 0.0)].iter() {
+        world.storage_mut().insert(entity, wall(z));
+        spatial_index.update(entity, &wall(z));
+    }
+    for &(entity, x) in [(projectile, 0.0), (missing, 5.0)].iter() {
+        world
+            .storage_mut()
+            .insert(entity, na::Point3::new(x, 0.0, 5.0));
+        world.storage_mut().insert(entity, ProjectileVelocity(20.0));
+        world.storage_mut().insert(
+            entity,
+            PreviousTransform {
+                position: na::Point3::new(x, 0.0, 0.5),
+                rotation: na::UnitQuaternion::identity(),
+            },
+        );
+    }
+    let mut hits = ProjectileHits::default();
+    let step = |hits: &mut ProjectileHits| {
+        ProjectileCollision::exec(
+            &world.entities(),
+            &mut world.storage_mut(),
+            &world.storage(),
+            &world.storage(),
+            &world.storage(),
+            &spatial_index,
+            &world.storage(),
+            &world.storage(),
+            &RuntimeConfiguration::new(),
+            hits,
+            world.commands(),
+        )
+    };
+    step(&mut hits);
+    assert_eq!(hits.0.len(), 1);
+    let hit = hits.0[0].clone();
+    assert_eq!((hit.projectile, hit.entity), (projectile, near_wall));
+    assert!((hit.point - na::Point3::new(0.0, 0.0, 2.0)).norm() < 1e-5);
+    assert!((hit.normal.into_inner() + na::Vector3::z()).norm() < 1e-5);
+    assert_eq!(world.storage().get(projectile), Some(&hit.point));
+    // the next step of the frame doesn't hit again before the despawn
+    step(&mut hits);
+    assert_eq!(hits.0, vec![hit]);
+    world.maintain();
+    assert!(!world.entities().is_alive(projectile));
+    assert!(world.entities().is_alive(missing));
+}
+
+#[test]
+fn test_sweep_triangles() {
+    use ncollide3d::{bounding_volume::AABB, query::Ray, shape::TriMesh};
+
+    // the lower left half of a square facing z, covering x and y from -1 to 1 at z = 3 in world
+    // space
+    let collision_mesh = TriMesh::new(
+        vec![
+            na::Point3::new(0.0, 0.0, 0.0),
+            na::Point3::new(1.0, 0.0, 0.0),
+            na::Point3::new(0.0, 1.0, 0.0),
+        ],
+        vec![na::Point3::new(0, 1, 2)],
+        None,
+    );
+    let model_matrix =
+        glm::translation(&glm::vec3(-1.0, -1.0, 3.0)) * glm::scaling(&glm::vec3(2.0, 2.0, 1.0));
+    let aabb = transform_aabb(
+        &AABB::new(na::Point3::origin(), na::Point3::new(1.0, 1.0, 0.0)),
+        &model_matrix,
+    );
+    let ray = Ray::new(
+        na::Point3::new(-0.5, -0.5, 0.0),
+        na::Vector3::new(0.0, 0.0, 4.0),
+    );
+    let hit = sweep(&ray, &aabb, Some((&model_matrix, &collision_mesh))).unwrap();
+    assert!((hit.toi - 0.75).abs() < 1e-5);
+    assert!(hit.normal.x.abs() < 1e-5 && hit.normal.y.abs() < 1e-5);
+    // too short to get there
+    let short = Ray::new(ray.origin, na::Vector3::new(0.0, 0.0, 2.0));
+    assert!(sweep(&short, &aabb, Some((&model_matrix, &collision_mesh))).is_none());
+    // through the AABB, but past the triangle
+    let corner = Ray::new(
+        na::Point3::new(0.8, 0.8, 0.0),
+        na::Vector3::new(0.0, 0.0, 4.0),
+    );
+    assert!(sweep(&corner, &aabb, None).is_some());
+    assert!(sweep(&corner, &aabb, Some((&model_matrix, &collision_mesh))).is_none());
+}
+
+#[test]
+fn test_cursor_ray() {
+    let mut camera = Camera {
+        position: na::Point3::new(0.0, 0.0, -5.0),
+        ..Camera::default()
+    };
+    ProjectCamera::project(2.0, &mut camera);
+    let ray = MousePicking::cursor_ray(&camera, (100.0, 50.0), (200.0, 100.0));
+    assert!((ray.origin - na::Point3::new(0.0, 0.0, -4.9)).norm() < 1e-3);
+    assert!((ray.dir.normalize() - na::Vector3::z()).norm() < 1e-5);
+    assert!((ray.dir.norm() - PICK_DISTANCE).abs() < 1e-2);
+    // the top right corner
+    let corner = MousePicking::cursor_ray(&camera, (200.0, 0.0), (200.0, 100.0));
+    assert!(corner.dir.x > 0.0 && corner.dir.y > 0.0);
+    assert!((corner.dir.x / corner.dir.y - 2.0).abs() < 1e-3);
+}
+
+#[test]
+fn test_mouse_picking() {
+    use ncollide3d::{bounding_volume::AABB, query::Ray};
+
+    let mut entities = EntitiesStorage::new();
+    let mut aabbs = ComponentStorage::new();
+    let mut spatial_index = SpatialIndex::new();
+    let cube =
+        |z: f32| AABB::from_half_extents(na::Point3::new(0.0, 0.0, z), na::Vector3::repeat(0.5));
+    let (far, near) = (entities.allocate(), entities.allocate());
+    for &(entity, z) in [(far, 10.0), (near, 5.0)].iter() {
+        aabbs.insert(entity, cube(z));
+        spatial_index.update(entity, &cube(z));
+    }
+    let pick = |ray: &Ray<f32>| {
+        MousePicking::pick(
+            ray,
+            true,
+            &entities,
+            &spatial_index,
+            &aabbs,
+            &ComponentStorage::new(),
+            &ComponentStorage::new(),
+        )
+    };
+    let ray = Ray::new(na::Point3::origin(), na::Vector3::new(0.0, 0.0, 100.0));
+    let (entity, point) = pick(&ray).unwrap();
+    assert_eq!(entity, near);
+    assert!((point - na::Point3::new(0.0, 0.0, 4.5)).norm() < 1e-4);
+    let above = Ray::new(
+        na::Point3::new(0.0, 2.0, 0.0),
+        na::Vector3::new(0.0, 0.0, 100.0),
+    );
+    assert_eq!(pick(&above), None);
+}
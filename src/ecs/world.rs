@@ -0,0 +1,296 @@
+use super::{commands::EntityCommands, custom::*};
+use hashbrown::HashMap;
+use parking_lot::{
+    MappedRwLockReadGuard, MappedRwLockWriteGuard, RwLock, RwLockReadGuard, RwLockWriteGuard,
+};
+use std::any::{type_name, Any, TypeId};
+
+/// Owns the entities, every registered component storage and all the resources, looked up
+/// by type. Borrows are checked at runtime and never block, conflicting ones panic instead.
+pub struct World {
+    resources: HashMap<TypeId, RwLock<Box<dyn Any + Send + Sync>>>,
+    // one per registered storage
+    storage_hooks: Vec<StorageHooks>,
+    commands: EntityCommands,
+}
+
+struct StorageHooks {
+    name: &'static str,
+    contains: fn(&World, u32) -> bool,
+    clear_changes: fn(&World),
+    // called with the freed entities
+    maintain: fn(&World, &croaring::Bitmap),
+}
+
+fn storage_contains<T: Send + Sync + 'static>(world: &World, entity_id: u32) -> bool {
+    world.storage::<T>().mask().contains(entity_id)
+}
+
+// strips the module paths, "alloc::vec::Vec<core::option::Option<u32>>" becomes
+// "Vec<Option<u32>>"
+fn short_type_name(name: &str) -> String {
+    let mut short = String::with_capacity(name.len());
+    let mut segment_start = 0;
+    for (ix, c) in name.char_indices() {
+        if c == ':' {
+            segment_start = ix + 1;
+        } else if !(c.is_alphanumeric() || c == '_') {
+            short.push_str(&name[segment_start..ix]);
+            short.push(c);
+            segment_start = ix + 1;
+        }
+    }
+    short.push_str(&name[segment_start..]);
+    short
+}
+
+fn clear_storage_changes<T: Send + Sync + 'static>(world: &World) {
+    world.storage_mut::<T>().clear_changes();
+}
+
+fn maintain_storage<T: Send + Sync + 'static>(world: &World, freed: &croaring::Bitmap) {
+    world.storage_mut::<T>().maintain(freed);
+}
+
+fn bury_storage<T: Send + Sync + 'static>(world: &World, freed: &croaring::Bitmap) {
+    let mut graveyard = world.write::<Graveyard>();
+    world
+        .storage_mut::<T>()
+        .maintain_with(freed, |component| graveyard.bury(component));
+}
+
+impl World {
+    pub fn new() -> World {
+        let mut world = World {
+            resources: HashMap::new(),
+            storage_hooks: vec![],
+            commands: EntityCommands::new(),
+        };
+        world.insert(EntitiesStorage::new());
+        world.insert(Graveyard::new());
+        world
+    }
+
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) {
+        let previous = self
+            .resources
+            .insert(TypeId::of::<T>(), RwLock::new(Box::new(value)));
+        debug_assert!(
+            previous.is_none(),
+            "{} inserted into the World twice",
+            type_name::<T>()
+        );
+    }
+
+    pub fn remove<T: Any + Send + Sync>(&mut self) -> Option<T> {
+        self.resources
+            .remove(&TypeId::of::<T>())
+            .map(|value| *value.into_inner().downcast().unwrap())
+    }
+
+    /// Adds an empty `ComponentStorage<T>` that gets maintained along with the entities
+    pub fn register<T: Send + Sync + 'static>(&mut self) {
+        self.register_with::<T>(maintain_storage::<T>);
+    }
+
+    /// Like `register()`, for components owning GPU resources. The ones of freed entities
+    /// are moved into the `Graveyard` instead of being dropped.
+    pub fn register_buried<T: Send + Sync + 'static>(&mut self) {
+        self.register_with::<T>(bury_storage::<T>);
+    }
+
+    fn register_with<T: Send + Sync + 'static>(&mut self, maintain: fn(&World, &croaring::Bitmap)) {
+        let storage = ComponentStorage::<T>::for_entities(&self.entities());
+        self.insert(storage);
+        self.storage_hooks.push(StorageHooks {
+            name: type_name::<T>(),
+            contains: storage_contains::<T>,
+            clear_changes: clear_storage_changes::<T>,
+            maintain,
+        });
+    }
+
+    fn lock<T: Any>(&self) -> &RwLock<Box<dyn Any + Send + Sync>> {
+        self.resources
+            .get(&TypeId::of::<T>())
+            .unwrap_or_else(|| panic!("{} is not in the World", type_name::<T>()))
+    }
+
+    pub fn read<T: Any>(&self) -> MappedRwLockReadGuard<'_, T> {
+        let guard = self
+            .lock::<T>()
+            .try_read()
+            .unwrap_or_else(|| panic!("{} is already borrowed mutably", type_name::<T>()));
+        RwLockReadGuard::map(guard, |value| value.downcast_ref().unwrap())
+    }
+
+    pub fn write<T: Any>(&self) -> MappedRwLockWriteGuard<'_, T> {
+        let guard = self
+            .lock::<T>()
+            .try_write()
+            .unwrap_or_else(|| panic!("{} is already borrowed", type_name::<T>()));
+        RwLockWriteGuard::map(guard, |value| value.downcast_mut().unwrap())
+    }
+
+    pub fn entities(&self) -> MappedRwLockReadGuard<'_, EntitiesStorage> {
+        self.read()
+    }
+
+    pub fn entities_mut(&self) -> MappedRwLockWriteGuard<'_, EntitiesStorage> {
+        self.write()
+    }
+
+    pub fn storage<T: 'static>(&self) -> MappedRwLockReadGuard<'_, ComponentStorage<T>> {
+        self.read()
+    }
+
+    pub fn storage_mut<T: 'static>(&self) -> MappedRwLockWriteGuard<'_, ComponentStorage<T>> {
+        self.write()
+    }
+
+    /// Short type names of the registered components the entity has
+    pub fn component_names(&self, entity_id: u32) -> Vec<String> {
+        self.storage_hooks
+            .iter()
+            .filter(|hooks| (hooks.contains)(self, entity_id))
+            .map(|hooks| short_type_name(hooks.name))
+            .collect()
+    }
+
+    /// Queue for structural changes that is usable with shared borrows
+    pub fn commands(&self) -> &EntityCommands {
+        &self.commands
+    }
+
+    /// Starts a new frame of change tracking in every registered storage, then applies the
+    /// queued commands, frees the entities removed since the last call and drops their
+    /// components. Changes made by both are seen during the next frame, so this needs to run
+    /// once at the end of the frame. Returns the freed entities.
+    pub fn maintain(&self) -> croaring::Bitmap {
+        for hooks in self.storage_hooks.iter() {
+            (hooks.clear_changes)(self);
+        }
+        self.maintain_entities()
+    }
+
+    /// Like `maintain()`, but keeps the changes recorded so far and adds the removals to them,
+    /// for freeing entities in between two `maintain()` calls.
+    pub fn maintain_entities(&self) -> croaring::Bitmap {
+        self.commands.apply(self);
+        let freed = self.entities_mut().maintain();
+        for hooks in self.storage_hooks.iter() {
+            (hooks.maintain)(self, &freed);
+        }
+        freed
+    }
+}
+
+#[test]
+fn test_maintain() {
+    let mut world = World::new();
+    world.register::<u32>();
+    world.register::<f32>();
+    let (first, second) = {
+        let mut entities = world.entities_mut();
+        (entities.allocate(), entities.allocate())
+    };
+    world.storage_mut::<u32>().insert(first, 1);
+    world.storage_mut::<u32>().insert(second, 2);
+    world.storage_mut::<f32>().insert(first, 1.0);
+    world.entities_mut().remove(first);
+    assert_eq!(world.maintain().to_vec(), vec![first.index]);
+    assert_eq!(world.storage::<u32>().mask().to_vec(), vec![second.index]);
+    assert!(world.storage::<f32>().mask().is_empty());
+    assert!(world.storage::<u32>().modified().is_empty());
+    assert!(world.storage::<u32>().inserted().is_empty());
+    assert_eq!(world.storage::<u32>().removed().to_vec(), vec![first.index]);
+    world.maintain();
+    assert!(world.storage::<u32>().removed().is_empty());
+}
+
+#[test]
+fn test_maintain_entities() {
+    let mut world = World::new();
+    world.register::<u32>();
+    let (first, second) = {
+        let mut entities = world.entities_mut();
+        (entities.allocate(), entities.allocate())
+    };
+    world.storage_mut::<u32>().insert(first, 1);
+    world.storage_mut::<u32>().insert(second, 2);
+    world.entities_mut().remove(first);
+    world.maintain();
+    world.entities_mut().remove(second);
+    assert_eq!(world.maintain_entities().to_vec(), vec![second.index]);
+    // both removals are seen until the next maintain()
+    assert_eq!(
+        world.storage::<u32>().removed().to_vec(),
+        vec![first.index, second.index]
+    );
+}
+
+#[test]
+fn test_maintain_buried() {
+    let mut world = World::new();
+    world.register_buried::<String>();
+    let entities = world.entities_mut().allocate_many(3);
+    for entity in entities.iter() {
+        world
+            .storage_mut::<String>()
+            .insert(*entity, format!("{:?}", entity));
+    }
+    world.entities_mut().remove(entities[0]);
+    world.maintain();
+    assert_eq!(world.storage::<String>().removed().to_vec(), vec![0]);
+    assert_eq!(world.read::<Graveyard>().len(), 1);
+    world.write::<Graveyard>().collect(31, 16);
+    world.entities_mut().remove(entities[1]);
+    world.maintain();
+    world.write::<Graveyard>().collect(47, 31);
+    assert_eq!(world.read::<Graveyard>().len(), 1);
+    world.write::<Graveyard>().collect(63, 47);
+    assert!(world.read::<Graveyard>().is_empty());
+}
+
+#[test]
+fn test_register_after_free() {
+    let mut world = World::new();
+    let first = world.entities_mut().allocate();
+    world.entities_mut().remove(first);
+    world.maintain();
+    let second = world.entities_mut().allocate();
+    assert_eq!(second.index, first.index);
+    world.register::<u32>();
+    world.storage_mut::<u32>().insert(second, 2);
+    assert_eq!(world.storage::<u32>().get(second), Some(&2));
+}
+
+#[test]
+fn test_component_names() {
+    let mut world = World::new();
+    world.register::<u32>();
+    world.register::<Vec<Option<f32>>>();
+    world.register::<na::Point3<f32>>();
+    let entity = world.entities_mut().allocate();
+    world.storage_mut::<u32>().insert(entity, 1);
+    world
+        .storage_mut::<na::Point3<f32>>()
+        .insert(entity, na::Point3::origin());
+    assert_eq!(
+        world.component_names(entity.index),
+        vec!["u32", "Point<f32, U3>"]
+    );
+    assert_eq!(
+        short_type_name(type_name::<Vec<Option<f32>>>()),
+        "Vec<Option<f32>>"
+    );
+}
+
+#[test]
+#[should_panic(expected = "already borrowed")]
+fn test_conflicting_borrow() {
+    let mut world = World::new();
+    world.register::<u32>();
+    let _storage = world.storage::<u32>();
+    let _conflicting = world.storage_mut::<u32>();
+}
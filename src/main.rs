@@ -6,19 +6,34 @@ extern crate nalgebra as na;
 extern crate nalgebra_glm as glm;
 
 pub mod ecs {
+    pub mod bindings;
+    pub mod camera_path;
+    pub mod commands;
     pub mod components;
     pub mod custom;
-    pub mod resources;
+    pub mod gizmo;
+    pub mod hierarchy;
+    pub mod inspector;
+    pub mod prefab;
+    pub mod replay;
+    pub mod scene;
+    pub mod scheduler;
+    pub mod snapshot;
+    pub mod spatial;
     pub mod systems;
+    pub mod world;
 }
 pub mod renderer;
 
 use ash::version::DeviceV1_0;
-use ecs::{components::*, custom::*, resources::*, systems::*};
+use ecs::{
+    bindings::*, camera_path::*, commands::*, components::*, custom::*, gizmo::*, prefab::*,
+    replay::*, scene::*, scheduler::*, snapshot::*, spatial::*, systems::*, world::*,
+};
+use imgui::im_str;
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
 #[cfg(feature = "microprofile")]
 use microprofile::scope;
-use na::RealField;
 use parking_lot::Mutex;
 use rayon;
 use renderer::*;
@@ -27,45 +42,73 @@ use std::sync::Arc;
 fn main() {
     #[cfg(feature = "profiling")]
     microprofile::init!();
-    let mut position_storage = ComponentStorage::<na::Point3<f32>>::new();
-    let mut rotation_storage = ComponentStorage::<na::UnitQuaternion<f32>>::new();
-    let mut scale_storage = ComponentStorage::<f32>::new();
-    let mut model_matrices_storage = ComponentStorage::<glm::Mat4>::new();
-    let mut aabb_storage = ComponentStorage::<ncollide3d::bounding_volume::AABB<f32>>::new();
-    let mut meshes_storage = ComponentStorage::<GltfMesh>::new();
-    let mut light_storage = ComponentStorage::<Light>::new();
-    let mut projectile_velocities_storage = ComponentStorage::<f32>::new();
-    let mut projectile_target_storage = ComponentStorage::<na::Point3<f32>>::new();
-    let mut base_color_texture_storage = ComponentStorage::<GltfMeshBaseColorTexture>::new();
-    let mut base_color_visited_storage = ComponentStorage::<BaseColorVisitedMarker>::new();
-    let mut coarse_culled_storage = ComponentStorage::<CoarseCulled>::new();
-    let mut shadow_mapping_light_matrices_storage =
-        ComponentStorage::<ShadowMappingLightMatrices>::new();
+    // --record <path> saves the input of the session on exit, --replay <path> plays one back,
+    // --camera-path <path> flies the camera along a path and reports the frame times,
+    // --print-schedule prints the stages of the schedules on startup
+    let mut record_path = None;
+    let mut input_replay = None;
+    let mut camera_path = None;
+    let mut print_schedule = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut path = || {
+            args.next()
+                .unwrap_or_else(|| panic!("{} requires a path", arg))
+        };
+        match arg.as_str() {
+            "--record" => record_path = Some(path()),
+            "--replay" => input_replay = Some(InputReplay::new(InputRecording::open(&path()))),
+            "--camera-path" => {
+                camera_path = Some(CameraPathPlayback::new(CameraPath::open(&path())))
+            }
+            "--print-schedule" => print_schedule = true,
+            _ => panic!("unknown argument {}", arg),
+        }
+    }
+    let mut input_recording = InputRecording::default();
+
+    let mut world = World::new();
+    world.register::<na::Point3<f32>>();
+    world.register::<na::UnitQuaternion<f32>>();
+    world.register::<Scale>();
+    world.register::<glm::Mat4>();
+    world.register::<Parent>();
+    world.register::<PreviousTransform>();
+    world.register::<ncollide3d::bounding_volume::AABB<f32>>();
+    world.register_buried::<GltfMesh>();
+    world.register::<Light>();
+    world.register::<ProjectileVelocity>();
+    world.register::<ProjectileTarget>();
+    world.register_buried::<GltfMeshBaseColorTexture>();
+    world.register_buried::<BaseColorVisitedMarker>();
+    world.register::<CoarseCulled>();
+    world.register_buried::<ShadowMappingLightMatrices>();
     rayon::ThreadPoolBuilder::new()
         .num_threads(8)
         .build_global()
         .unwrap();
-    let (mut renderer, mut swapchain, events_loop) = RenderFrame::new();
+    let (renderer, mut swapchain, events_loop) = RenderFrame::new();
 
     let quit_handle = Arc::new(Mutex::new(false));
 
     let mut present_data = PresentData::new(&renderer);
-    let mut image_index = ImageIndex::default();
-    let mut frame_timing = FrameTiming::default();
-    let mut input_state = InputState::default();
-    let mut camera = Camera::default();
+    let image_index = ImageIndex::default();
+    let frame_timing = FrameTiming::default();
+    let input_state = InputState::default();
+    let key_bindings = KeyBindings::open_or_default(KEY_BINDINGS_PATH);
+    let actions = ActionState::default();
+    let camera = Camera::default();
     let mut fly_camera = FlyCamera::default();
-    let mut consolidated_mesh_buffers = ConsolidatedMeshBuffers::new(&renderer);
-    let mut graphics_command_pool = GraphicsCommandPool::new(&renderer);
-
-    let mut entities = EntitiesStorage::new();
+    let mut orbit_camera = OrbitCamera::default();
+    let consolidated_mesh_buffers = ConsolidatedMeshBuffers::new(&renderer);
+    let graphics_command_pool = GraphicsCommandPool::new(&renderer);
 
     let mut main_descriptor_pool = MainDescriptorPool::new(&renderer);
-    let mut camera_matrices = CameraMatrices::new(&renderer, &main_descriptor_pool);
+    let camera_matrices = CameraMatrices::new(&renderer, &main_descriptor_pool);
 
     let base_color_descriptor_set =
         BaseColorDescriptorSet::new(&renderer, &mut main_descriptor_pool);
-    let mut model_data = ModelData::new(&renderer, &main_descriptor_pool);
+    let model_data = ModelData::new(&renderer, &main_descriptor_pool);
 
     let cull_pass_data = CullPassData::new(
         &renderer,
@@ -85,9 +128,40 @@ fn main() {
     let mut shadow_mapping_data =
         ShadowMappingData::new(&renderer, &depth_pass_data, &mut main_descriptor_pool);
 
-    let mut runtime_config = RuntimeConfiguration::new();
+    let runtime_config = RuntimeConfiguration::new();
 
     let mut gui = Gui::new();
+    gui.inspector
+        .register(|ui, position: &mut na::Point3<f32>| {
+            let mut coords = [position.x, position.y, position.z];
+            let changed = ui.input_float3(&im_str!("position"), &mut coords).build();
+            *position = na::Point3::from(coords);
+            changed
+        });
+    gui.inspector
+        .register(|ui, rotation: &mut na::UnitQuaternion<f32>| {
+            let (roll, pitch, yaw) = rotation.euler_angles();
+            let mut degrees = [roll.to_degrees(), pitch.to_degrees(), yaw.to_degrees()];
+            let changed = ui.input_float3(&im_str!("rotation"), &mut degrees).build();
+            if changed {
+                *rotation = na::UnitQuaternion::from_euler_angles(
+                    degrees[0].to_radians(),
+                    degrees[1].to_radians(),
+                    degrees[2].to_radians(),
+                );
+            }
+            changed
+        });
+    gui.inspector.register(|ui, scale: &mut Scale| {
+        let mut axes = [scale.0.x, scale.0.y, scale.0.z];
+        let changed = ui.input_float3(&im_str!("scale"), &mut axes).build();
+        scale.0 = na::Vector3::from(axes);
+        changed
+    });
+    gui.inspector.register(|ui, light: &mut Light| {
+        ui.input_float(&im_str!("light strength"), &mut light.strength)
+            .build()
+    });
     let mut gui_render = GuiRender::new(&renderer, &main_descriptor_pool, &mut gui);
     let mut imgui_platform = WinitPlatform::init(&mut gui.imgui);
     imgui_platform.attach_window(
@@ -111,227 +185,288 @@ fn main() {
     );
 
     let debug_aabb_pass_data = DebugAABBPassData::new(&renderer, &camera_matrices);
+    let debug_gizmo_pass_data = DebugGizmoPassData::new(&renderer, &camera_matrices);
 
     let mut main_framebuffer = MainFramebuffer::new(&renderer, &main_attachments, &swapchain);
 
-    let LoadedMesh {
-        vertex_buffer,
-        normal_buffer,
-        uv_buffer,
-        index_buffers,
-        vertex_len,
-        aabb,
-        base_color,
-    } = load_gltf(
+    let mut scene_loader = SceneLoader::new();
+    scene_loader.load(
         &renderer,
         &graphics_command_pool,
-        "vendor/glTF-Sample-Models/2.0/SciFiHelmet/glTF/SciFiHelmet.gltf",
-    );
-
-    let vertex_buffer = Arc::new(vertex_buffer);
-    let normal_buffer = Arc::new(normal_buffer);
-    let uv_buffer = Arc::new(uv_buffer);
-    let index_buffers = Arc::new(index_buffers);
-    let base_color = Arc::new(base_color);
-
-    let max_entities = 30;
-    debug_assert!(max_entities > 7); // 7 static ones
-
-    let ixes = entities.allocate_mask(max_entities);
-    debug_assert_eq!(ixes.to_vec(), (0..max_entities).collect::<Vec<_>>());
-
-    position_storage.replace_mask(&ixes);
-    rotation_storage.replace_mask(&ixes);
-
-    let mut light_only = croaring::Bitmap::create();
-    light_only.add_many(&[0, 1]);
-    light_storage.replace_mask(&light_only);
-    let rest = ixes - light_only;
-    scale_storage.replace_mask(&rest);
-    meshes_storage.replace_mask(&rest);
-    base_color_texture_storage.replace_mask(&rest);
-
-    position_storage.insert(0, na::Point3::new(30.0, 20.0, -40.1));
-    rotation_storage.insert(
-        0,
-        na::UnitQuaternion::look_at_lh(
-            &(na::Point3::new(0.0, 0.0, 0.0) - na::Point3::new(30.0, 20.0, -40.1)),
-            &up_vector(),
-        ),
-    );
-    light_storage.insert(0, Light { strength: 1.0 });
-
-    position_storage.insert(1, na::Point3::new(0.1, 17.0, 0.1));
-    rotation_storage.insert(
-        1,
-        na::UnitQuaternion::look_at_lh(
-            &(na::Point3::new(0.0, 0.0, 0.0) - na::Point3::new(0.1, 17.0, 0.1)),
-            &up_vector(),
-        ),
+        &world,
+        "scenes/main.json",
     );
-    light_storage.insert(1, Light { strength: 0.7 });
-
-    position_storage.insert(2, na::Point3::new(0.0, 5.0, 0.0));
-    rotation_storage.insert(2, na::UnitQuaternion::identity());
-    scale_storage.insert(2, 1.0);
-    meshes_storage.insert(
-        2,
-        GltfMesh {
-            vertex_buffer: Arc::clone(&vertex_buffer),
-            normal_buffer: Arc::clone(&normal_buffer),
-            uv_buffer: Arc::clone(&uv_buffer),
-            index_buffers: Arc::clone(&index_buffers),
-            vertex_len,
-            aabb: aabb.clone(),
-        },
-    );
-    base_color_texture_storage.insert(2, GltfMeshBaseColorTexture(Arc::clone(&base_color)));
-
-    position_storage.insert(3, na::Point3::new(0.0, 5.0, 5.0));
-    rotation_storage.insert(
-        3,
-        na::UnitQuaternion::from_axis_angle(&up_vector(), f32::pi() / 2.0),
-    );
-    scale_storage.insert(3, 1.0);
-    meshes_storage.insert(
-        3,
-        GltfMesh {
-            vertex_buffer: Arc::clone(&vertex_buffer),
-            normal_buffer: Arc::clone(&normal_buffer),
-            uv_buffer: Arc::clone(&uv_buffer),
-            index_buffers: Arc::clone(&index_buffers),
-            vertex_len,
-            aabb: aabb.clone(),
-        },
-    );
-    base_color_texture_storage.insert(3, GltfMeshBaseColorTexture(Arc::clone(&base_color)));
-
-    position_storage.insert(4, na::Point3::new(-5.0, 5.0, 0.0));
-    rotation_storage.insert(
-        4,
-        na::UnitQuaternion::from_axis_angle(&up_vector(), f32::pi() / 3.0),
-    );
-    scale_storage.insert(4, 1.0);
-    meshes_storage.insert(
-        4,
-        GltfMesh {
-            vertex_buffer: Arc::clone(&vertex_buffer),
-            normal_buffer: Arc::clone(&normal_buffer),
-            uv_buffer: Arc::clone(&uv_buffer),
-            index_buffers: Arc::clone(&index_buffers),
-            vertex_len,
-            aabb: aabb.clone(),
+    let mut prefabs = Prefabs::new();
+    prefabs.register(
+        &renderer,
+        &graphics_command_pool,
+        &mut scene_loader,
+        "projectile",
+        Prefab {
+            projectile_velocity: Some(20.0),
+            mesh: Some(
+                "vendor/glTF-Sample-Models/2.0/BoxTextured/glTF/BoxTextured.gltf".to_string(),
+            ),
+            ..Prefab::default()
         },
     );
-    base_color_texture_storage.insert(4, GltfMeshBaseColorTexture(Arc::clone(&base_color)));
 
-    let (
-        box_vertex_buffer,
-        box_normal_buffer,
-        box_uv_buffer,
-        box_index_buffers,
-        box_base_color,
-        box_vertex_len,
-        box_aabb,
-    ) = {
-        let LoadedMesh {
-            vertex_buffer,
-            normal_buffer,
-            uv_buffer,
-            index_buffers,
-            vertex_len,
-            aabb,
-            base_color,
-        } = {
-            load_gltf(
-                &renderer,
-                &graphics_command_pool,
-                "vendor/glTF-Sample-Models/2.0/BoxTextured/glTF/BoxTextured.gltf",
-            )
-        };
-
-        (
-            Arc::new(vertex_buffer),
-            Arc::new(normal_buffer),
-            Arc::new(uv_buffer),
-            Arc::new(index_buffers),
-            Arc::new(base_color),
-            vertex_len,
-            aabb,
+    world.insert(renderer);
+    world.insert(image_index);
+    world.insert(frame_timing);
+    world.insert(input_state);
+    world.insert(key_bindings);
+    world.insert(actions);
+    world.insert(camera);
+    world.insert(consolidated_mesh_buffers);
+    world.insert(graphics_command_pool);
+    world.insert(main_descriptor_pool);
+    world.insert(camera_matrices);
+    world.insert(base_color_descriptor_set);
+    world.insert(model_data);
+    world.insert(runtime_config);
+    world.insert(prefabs);
+    world.insert(ProjectileHits::default());
+    world.insert(SpatialIndex::new());
+    world.insert(Selected::default());
+    world.insert(Gizmo::new());
+
+    // runs FrameTiming::steps() times per frame, before the frame schedule
+    let mut simulation = Schedule::new();
+    simulation
+        .add_system(
+            Access::new("UpdateProjectiles")
+                .reads::<EntitiesStorage>()
+                .writes::<ComponentStorage<na::Point3<f32>>>()
+                .reads::<ComponentStorage<na::UnitQuaternion<f32>>>()
+                .reads::<ComponentStorage<ProjectileTarget>>()
+                .reads::<ComponentStorage<ProjectileVelocity>>()
+                .writes::<ComponentStorage<PreviousTransform>>(),
+            |world| {
+                UpdateProjectiles::exec(
+                    &world.entities(),
+                    &mut world.storage_mut::<na::Point3<f32>>(),
+                    &world.storage::<na::UnitQuaternion<f32>>(),
+                    &world.storage::<ProjectileTarget>(),
+                    &world.storage::<ProjectileVelocity>(),
+                    &mut world.storage_mut::<PreviousTransform>(),
+                    world.commands(),
+                );
+            },
         )
-    };
-
-    let mesh_library = MeshLibrary {
-        projectile: GltfMesh {
-            vertex_buffer: Arc::clone(&box_vertex_buffer),
-            normal_buffer: Arc::clone(&box_normal_buffer),
-            uv_buffer: Arc::clone(&box_uv_buffer),
-            index_buffers: Arc::clone(&box_index_buffers),
-            vertex_len: box_vertex_len,
-            aabb: box_aabb.clone(),
-        },
-        projectile_texture: Arc::clone(&box_base_color),
-    };
-
-    position_storage.insert(5, na::Point3::new(5.0, 3.0, 2.0));
-    rotation_storage.insert(5, na::UnitQuaternion::identity());
-    scale_storage.insert(5, 1.0);
-    meshes_storage.insert(
-        5,
-        GltfMesh {
-            vertex_buffer: Arc::clone(&box_vertex_buffer),
-            normal_buffer: Arc::clone(&box_normal_buffer),
-            uv_buffer: Arc::clone(&box_uv_buffer),
-            index_buffers: Arc::clone(&box_index_buffers),
-            vertex_len: box_vertex_len,
-            aabb: box_aabb.clone(),
-        },
-    );
-    base_color_texture_storage.insert(5, GltfMeshBaseColorTexture(Arc::clone(&box_base_color)));
-
-    position_storage.insert(6, na::Point3::new(0.0, -29.0, 0.0));
-    rotation_storage.insert(6, na::UnitQuaternion::identity());
-    scale_storage.insert(6, 50.0);
-    meshes_storage.insert(
-        6,
-        GltfMesh {
-            vertex_buffer: Arc::clone(&box_vertex_buffer),
-            normal_buffer: Arc::clone(&box_normal_buffer),
-            uv_buffer: Arc::clone(&box_uv_buffer),
-            index_buffers: Arc::clone(&box_index_buffers),
-            vertex_len: box_vertex_len,
-            aabb: box_aabb.clone(),
-        },
-    );
-    base_color_texture_storage.insert(6, GltfMeshBaseColorTexture(Arc::clone(&box_base_color)));
-
-    for ix in 7..max_entities {
-        let angle = f32::pi() * (ix as f32 * 20.0) / 180.0;
-        let rot = na::Rotation3::from_axis_angle(&na::Unit::new_normalize(na::Vector3::y()), angle);
-        let pos = rot.transform_point(&na::Point3::new(
-            0.0,
-            (ix as f32 * -0.01) + 2.0,
-            5.0 + (ix / 10) as f32,
-        ));
-
-        position_storage.insert(ix, pos);
-        rotation_storage.insert(
-            ix,
-            na::UnitQuaternion::from_axis_angle(&na::Unit::new_normalize(na::Vector3::y()), angle),
+        .add_system(
+            Access::new("ProjectileCollision")
+                .reads::<EntitiesStorage>()
+                .writes::<ComponentStorage<na::Point3<f32>>>()
+                .reads::<ComponentStorage<ProjectileVelocity>>()
+                .reads::<ComponentStorage<PreviousTransform>>()
+                .reads::<ComponentStorage<ncollide3d::bounding_volume::AABB<f32>>>()
+                .reads::<SpatialIndex>()
+                .reads::<ComponentStorage<glm::Mat4>>()
+                .reads::<ComponentStorage<GltfMesh>>()
+                .reads::<RuntimeConfiguration>()
+                .writes::<ProjectileHits>(),
+            |world| {
+                ProjectileCollision::exec(
+                    &world.entities(),
+                    &mut world.storage_mut::<na::Point3<f32>>(),
+                    &world.storage::<ProjectileVelocity>(),
+                    &world.storage::<PreviousTransform>(),
+                    &world.storage::<ncollide3d::bounding_volume::AABB<f32>>(),
+                    &world.read::<SpatialIndex>(),
+                    &world.storage::<glm::Mat4>(),
+                    &world.storage::<GltfMesh>(),
+                    &world.read::<RuntimeConfiguration>(),
+                    &mut world.write::<ProjectileHits>(),
+                    world.commands(),
+                );
+            },
         );
-        scale_storage.insert(ix, 0.6);
-        meshes_storage.insert(
-            ix,
-            GltfMesh {
-                vertex_buffer: Arc::clone(&vertex_buffer),
-                normal_buffer: Arc::clone(&normal_buffer),
-                uv_buffer: Arc::clone(&uv_buffer),
-                index_buffers: Arc::clone(&index_buffers),
-                vertex_len,
-                aabb: aabb.clone(),
+
+    let mut schedule = Schedule::new();
+    schedule
+        .add_system(
+            Access::new("LaunchProjectileTest")
+                .reads::<Camera>()
+                .reads::<Prefabs>()
+                .reads::<ActionState>()
+                .reads::<RuntimeConfiguration>(),
+            |world| {
+                LaunchProjectileTest::exec(
+                    &world.read::<Camera>(),
+                    &world.read::<Prefabs>(),
+                    &world.read::<ActionState>(),
+                    &world.read::<RuntimeConfiguration>(),
+                    world.commands(),
+                );
+            },
+        )
+        .add_system(
+            Access::new("ConsolidateMeshBuffers")
+                .reads::<RenderFrame>()
+                .reads::<EntitiesStorage>()
+                .reads::<GraphicsCommandPool>()
+                .reads::<ComponentStorage<GltfMesh>>()
+                .reads::<ImageIndex>()
+                .writes::<ConsolidatedMeshBuffers>(),
+            |world| {
+                ConsolidateMeshBuffers::exec(
+                    &world.read::<RenderFrame>(),
+                    &world.entities(),
+                    &world.read::<GraphicsCommandPool>(),
+                    &world.storage::<GltfMesh>(),
+                    &world.read::<ImageIndex>(),
+                    &mut world.write::<ConsolidatedMeshBuffers>(),
+                );
+            },
+        )
+        .add_system(
+            Access::new("ModelMatrixCalculation")
+                .reads::<EntitiesStorage>()
+                .reads::<FrameTiming>()
+                .reads::<ComponentStorage<na::Point3<f32>>>()
+                .reads::<ComponentStorage<na::UnitQuaternion<f32>>>()
+                .reads::<ComponentStorage<Scale>>()
+                .reads::<ComponentStorage<Parent>>()
+                .reads::<ComponentStorage<PreviousTransform>>()
+                .writes::<ComponentStorage<glm::Mat4>>(),
+            |world| {
+                ModelMatrixCalculation::exec(
+                    &world.entities(),
+                    &world.read::<FrameTiming>(),
+                    &world.storage::<na::Point3<f32>>(),
+                    &world.storage::<na::UnitQuaternion<f32>>(),
+                    &world.storage::<Scale>(),
+                    &world.storage::<Parent>(),
+                    &world.storage::<PreviousTransform>(),
+                    &mut world.storage_mut::<glm::Mat4>(),
+                );
+            },
+        )
+        .add_system(
+            Access::new("AABBCalculation")
+                .reads::<EntitiesStorage>()
+                .reads::<ComponentStorage<glm::Mat4>>()
+                .reads::<ComponentStorage<GltfMesh>>()
+                .writes::<ComponentStorage<ncollide3d::bounding_volume::AABB<f32>>>(),
+            |world| {
+                AABBCalculation::exec(
+                    &world.entities(),
+                    &world.storage::<glm::Mat4>(),
+                    &world.storage::<GltfMesh>(),
+                    &mut world.storage_mut::<ncollide3d::bounding_volume::AABB<f32>>(),
+                );
+            },
+        )
+        .add_system(
+            Access::new("UpdateSpatialIndex")
+                .reads::<EntitiesStorage>()
+                .reads::<ComponentStorage<ncollide3d::bounding_volume::AABB<f32>>>()
+                .writes::<SpatialIndex>(),
+            |world| {
+                UpdateSpatialIndex::exec(
+                    &world.entities(),
+                    &world.storage::<ncollide3d::bounding_volume::AABB<f32>>(),
+                    &mut world.write::<SpatialIndex>(),
+                );
+            },
+        )
+        .add_system(
+            Access::new("ShadowMappingMVPCalculation")
+                .reads::<RenderFrame>()
+                .reads::<EntitiesStorage>()
+                .reads::<ComponentStorage<na::Point3<f32>>>()
+                .reads::<ComponentStorage<na::UnitQuaternion<f32>>>()
+                .reads::<ComponentStorage<Parent>>()
+                .reads::<ComponentStorage<glm::Mat4>>()
+                .reads::<ComponentStorage<Light>>()
+                .reads::<ImageIndex>()
+                .reads::<MainDescriptorPool>()
+                .reads::<CameraMatrices>()
+                .writes::<ComponentStorage<ShadowMappingLightMatrices>>()
+                .writes::<Graveyard>(),
+            |world| {
+                ShadowMappingMVPCalculation::exec(
+                    &world.read::<RenderFrame>(),
+                    &world.entities(),
+                    &world.storage::<na::Point3<f32>>(),
+                    &world.storage::<na::UnitQuaternion<f32>>(),
+                    &world.storage::<Parent>(),
+                    &world.storage::<glm::Mat4>(),
+                    &mut world.storage_mut::<ShadowMappingLightMatrices>(),
+                    &world.storage::<Light>(),
+                    &world.read::<ImageIndex>(),
+                    &world.read::<MainDescriptorPool>(),
+                    &world.read::<CameraMatrices>(),
+                    &mut world.write::<Graveyard>(),
+                );
+            },
+        )
+        .add_system(
+            Access::new("CoarseCulling")
+                .reads::<EntitiesStorage>()
+                .reads::<ComponentStorage<ncollide3d::bounding_volume::AABB<f32>>>()
+                .reads::<SpatialIndex>()
+                .reads::<Camera>()
+                .writes::<ComponentStorage<CoarseCulled>>(),
+            |world| {
+                CoarseCulling::exec(
+                    &world.entities(),
+                    &world.storage::<ncollide3d::bounding_volume::AABB<f32>>(),
+                    &world.read::<SpatialIndex>(),
+                    &world.read::<Camera>(),
+                    &mut world.storage_mut::<CoarseCulled>(),
+                );
+            },
+        )
+        .add_system(
+            Access::new("SynchronizeBaseColorTextures")
+                .reads::<EntitiesStorage>()
+                .reads::<RenderFrame>()
+                .reads::<BaseColorDescriptorSet>()
+                .reads::<ComponentStorage<GltfMeshBaseColorTexture>>()
+                .reads::<ImageIndex>()
+                .writes::<ComponentStorage<BaseColorVisitedMarker>>(),
+            |world| {
+                SynchronizeBaseColorTextures::exec(
+                    &world.entities(),
+                    &world.read::<RenderFrame>(),
+                    &world.read::<BaseColorDescriptorSet>(),
+                    &world.storage::<GltfMeshBaseColorTexture>(),
+                    &world.read::<ImageIndex>(),
+                    &mut world.storage_mut::<BaseColorVisitedMarker>(),
+                );
+            },
+        )
+        .add_system(
+            Access::new("CameraMatricesUpload")
+                .reads::<ImageIndex>()
+                .reads::<Camera>()
+                .writes::<CameraMatrices>(),
+            |world| {
+                CameraMatricesUpload::exec(
+                    &world.read::<ImageIndex>(),
+                    &world.read::<Camera>(),
+                    &mut world.write::<CameraMatrices>(),
+                );
+            },
+        )
+        .add_system(
+            Access::new("ModelMatricesUpload")
+                .reads::<ComponentStorage<glm::Mat4>>()
+                .reads::<ImageIndex>()
+                .writes::<ModelData>(),
+            |world| {
+                ModelMatricesUpload::exec(
+                    &world.storage::<glm::Mat4>(),
+                    &world.read::<ImageIndex>(),
+                    &mut world.write::<ModelData>(),
+                );
             },
         );
-        base_color_texture_storage.insert(ix, GltfMeshBaseColorTexture(Arc::clone(&base_color)));
+    if print_schedule {
+        println!("simulation schedule:\n{:?}", simulation);
+        println!("frame schedule:\n{:?}", schedule);
     }
 
     'frame: loop {
@@ -342,237 +477,323 @@ fn main() {
         {
             #[cfg(feature = "profiling")]
             microprofile::scope!("game-loop", "ecs");
-            let window_resized = input_handler.exec(
-                &renderer.instance.window,
-                &mut gui.imgui,
-                &mut input_state,
-                &mut camera,
-                &mut runtime_config,
-            );
-
-            if window_resized {
-                unsafe {
-                    renderer.device.device_wait_idle().unwrap();
+            {
+                let renderer = world.read::<RenderFrame>();
+                let mut image_index = world.write::<ImageIndex>();
+                let mut frame_timing = world.write::<FrameTiming>();
+                let mut input_state = world.write::<InputState>();
+                let mut key_bindings = world.write::<KeyBindings>();
+                let mut actions = world.write::<ActionState>();
+                let mut camera = world.write::<Camera>();
+                let mut runtime_config = world.write::<RuntimeConfiguration>();
+                let model_data = world.read::<ModelData>();
+                let camera_matrices = world.read::<CameraMatrices>();
+
+                let window_resized =
+                    input_handler.exec(&renderer.instance.window, &mut gui.imgui, &mut input_state);
+
+                if window_resized {
+                    unsafe {
+                        renderer.device.device_wait_idle().unwrap();
+                    }
+                    swapchain.resize_to_fit();
+                    main_attachments = MainAttachments::new(&renderer, &swapchain);
+                    depth_pass_data = DepthPassData::new(
+                        &renderer,
+                        &model_data,
+                        &main_attachments,
+                        &swapchain,
+                        &camera_matrices,
+                    );
+                    main_framebuffer =
+                        MainFramebuffer::new(&renderer, &main_attachments, &swapchain);
+                    present_data = PresentData::new(&renderer);
                 }
-                swapchain.resize_to_fit();
-                main_attachments = MainAttachments::new(&renderer, &swapchain);
-                depth_pass_data = DepthPassData::new(
+
+                let swapchain_needs_recreating = AcquireFramebuffer::exec(
                     &renderer,
-                    &model_data,
-                    &main_attachments,
+                    &present_data,
                     &swapchain,
-                    &camera_matrices,
+                    &mut image_index,
                 );
-                main_framebuffer = MainFramebuffer::new(&renderer, &main_attachments, &swapchain);
-                present_data = PresentData::new(&renderer);
-            }
+                if swapchain_needs_recreating {
+                    unsafe {
+                        renderer.device.device_wait_idle().unwrap();
+                    }
+                    swapchain.resize_to_fit();
+                    main_attachments = MainAttachments::new(&renderer, &swapchain);
+                    depth_pass_data = DepthPassData::new(
+                        &renderer,
+                        &model_data,
+                        &main_attachments,
+                        &swapchain,
+                        &camera_matrices,
+                    );
+                    main_framebuffer =
+                        MainFramebuffer::new(&renderer, &main_attachments, &swapchain);
+                    present_data = PresentData::new(&renderer);
+                    AcquireFramebuffer::exec(
+                        &renderer,
+                        &present_data,
+                        &swapchain,
+                        &mut image_index,
+                    );
+                }
 
-            let swapchain_needs_recreating =
-                AcquireFramebuffer::exec(&renderer, &present_data, &swapchain, &mut image_index);
-            if swapchain_needs_recreating {
-                unsafe {
-                    renderer.device.device_wait_idle().unwrap();
+                let window_size = (swapchain.width, swapchain.height);
+                match input_replay {
+                    Some(ref mut replay) => {
+                        if !replay.exec(&mut input_state, &mut frame_timing, window_size) {
+                            *quit_handle.lock() = true;
+                        }
+                    }
+                    None => CalculateFrameTiming::exec(&mut frame_timing),
                 }
-                swapchain.resize_to_fit();
-                main_attachments = MainAttachments::new(&renderer, &swapchain);
-                depth_pass_data = DepthPassData::new(
+                if record_path.is_some() {
+                    input_recording.record(&input_state, &frame_timing, window_size);
+                }
+                gui.key_bindings_editor
+                    .capture(&mut input_state, &mut key_bindings);
+                MapActions::exec(&input_state, &key_bindings, &mut actions);
+                if actions.pressed(Action::Quit) {
+                    *quit_handle.lock() = true;
+                }
+                if let Some(ref mut playback) = camera_path {
+                    if !playback.exec(&frame_timing, &mut camera) {
+                        *quit_handle.lock() = true;
+                    }
+                } else {
+                    let entities = world.entities();
+                    let positions = world.storage::<na::Point3<f32>>();
+                    SelectCameraController::exec(
+                        &actions,
+                        world.read::<Selected>().entity,
+                        &entities,
+                        &positions,
+                        &camera,
+                        &mut runtime_config,
+                    );
+                    fly_camera.exec(
+                        &input_state,
+                        &actions,
+                        &frame_timing,
+                        &runtime_config,
+                        &mut camera,
+                    );
+                    orbit_camera.exec(
+                        &input_state,
+                        &actions,
+                        &frame_timing,
+                        &runtime_config,
+                        &mut camera,
+                    );
+                    FollowCamera::exec(
+                        &entities,
+                        &positions,
+                        &world.storage(),
+                        &frame_timing,
+                        &mut runtime_config,
+                        &mut camera,
+                    );
+                }
+                if let Some(projection_mode) = runtime_config.projection_mode.take() {
+                    camera.projection_mode = projection_mode;
+                }
+                ProjectCamera::exec(&swapchain, &mut camera);
+                ManipulateGizmo::exec(
+                    &input_state,
+                    &actions,
+                    &runtime_config,
+                    &swapchain,
+                    &camera,
+                    world.read::<Selected>().entity,
+                    &world.entities(),
+                    &world.storage(),
+                    &world.storage(),
+                    &mut world.storage_mut(),
+                    &mut world.storage_mut(),
+                    &mut world.storage_mut(),
+                    &mut world.write::<Gizmo>(),
+                );
+                MousePicking::exec(
+                    &input_state,
+                    &actions,
+                    &runtime_config,
+                    &swapchain,
+                    &camera,
+                    &world.entities(),
+                    &world.read::<SpatialIndex>(),
+                    &world.storage(),
+                    &world.storage(),
+                    &world.storage(),
+                    &world.read::<Gizmo>(),
+                    &mut world.write::<Selected>(),
+                );
+            }
+            world.write::<ProjectileHits>().0.clear();
+            let steps = world.read::<FrameTiming>().steps();
+            for _ in 0..steps {
+                simulation.run(&world);
+            }
+            schedule.run(&world);
+            {
+                let renderer = world.read::<RenderFrame>();
+                let entities = world.entities();
+                let image_index = world.read::<ImageIndex>();
+                let camera = world.read::<Camera>();
+                let mut runtime_config = world.write::<RuntimeConfiguration>();
+                let mut graphics_command_pool = world.write::<GraphicsCommandPool>();
+                let consolidated_mesh_buffers = world.read::<ConsolidatedMeshBuffers>();
+                let base_color_descriptor_set = world.read::<BaseColorDescriptorSet>();
+                let camera_matrices = world.read::<CameraMatrices>();
+                let model_data = world.read::<ModelData>();
+                let meshes_storage = world.storage::<GltfMesh>();
+                let position_storage = world.storage::<na::Point3<f32>>();
+                let light_storage = world.storage::<Light>();
+                let aabb_storage = world.storage::<ncollide3d::bounding_volume::AABB<f32>>();
+                let model_matrices_storage = world.storage::<glm::Mat4>();
+                let rotation_storage = world.storage::<na::UnitQuaternion<f32>>();
+                let shadow_mapping_light_matrices_storage =
+                    world.storage::<ShadowMappingLightMatrices>();
+
+                CullPass::exec(
+                    &entities,
+                    &renderer,
+                    &cull_pass_data,
+                    &mut cull_pass_data_private,
+                    &meshes_storage,
+                    &image_index,
+                    &consolidated_mesh_buffers,
+                    &position_storage,
+                    &camera,
+                    &model_data,
+                    &camera_matrices,
+                );
+                PrepareShadowMaps::exec(
+                    &entities,
                     &renderer,
+                    &depth_pass_data,
+                    &image_index,
+                    &mut graphics_command_pool,
+                    &mut shadow_mapping_data,
+                    &meshes_storage,
+                    &light_storage,
+                    &shadow_mapping_light_matrices_storage,
                     &model_data,
-                    &main_attachments,
+                );
+                DepthOnlyPass::exec(
+                    &renderer,
+                    &runtime_config,
+                    &entities,
+                    &image_index,
+                    &meshes_storage,
+                    &position_storage,
+                    &camera,
+                    &camera_matrices,
+                    &mut depth_pass_data,
+                    &swapchain,
+                    &model_data,
+                    &mut graphics_command_pool,
+                );
+                let gizmo_lines = match world.read::<Selected>().entity {
+                    Some(entity)
+                        if entities.is_alive(entity)
+                            && (&*model_matrices_storage, &*rotation_storage)
+                                .join_mask()
+                                .contains(entity.index) =>
+                    {
+                        world.read::<Gizmo>().lines(
+                            &camera,
+                            model_matrices_storage.get(entity).unwrap(),
+                            rotation_storage.get(entity).unwrap(),
+                        )
+                    }
+                    _ => vec![],
+                };
+                let gui_draw_data = gui.update(
+                    &renderer,
+                    &input_handler,
                     &swapchain,
+                    &camera,
+                    &mut runtime_config,
+                    &world,
+                );
+                Renderer::exec(
+                    &renderer,
+                    &runtime_config,
+                    &main_framebuffer,
+                    &swapchain,
+                    &entities,
+                    &debug_aabb_pass_data,
+                    &aabb_storage,
+                    world.read::<Selected>().entity,
+                    &debug_gizmo_pass_data,
+                    &gizmo_lines,
+                    &mut gui_render,
+                    &gui_draw_data,
+                    &base_color_descriptor_set,
+                    &consolidated_mesh_buffers,
+                    &cull_pass_data,
+                    &mut present_data,
+                    &image_index,
+                    &model_data,
+                    &gltf_pass,
+                    &mut graphics_command_pool,
+                    &shadow_mapping_data,
                     &camera_matrices,
                 );
-                main_framebuffer = MainFramebuffer::new(&renderer, &main_attachments, &swapchain);
-                present_data = PresentData::new(&renderer);
-                AcquireFramebuffer::exec(&renderer, &present_data, &swapchain, &mut image_index);
+                PresentFramebuffer::exec(&renderer, &present_data, &swapchain, &image_index);
             }
-
-            CalculateFrameTiming::exec(&mut frame_timing);
-            fly_camera.exec(&input_state, &frame_timing, &runtime_config, &mut camera);
-            ProjectCamera::exec(&swapchain, &mut camera);
-            LaunchProjectileTest::exec(
-                &mut entities,
-                &mut position_storage,
-                &mut rotation_storage,
-                &mut scale_storage,
-                &mut meshes_storage,
-                &mut base_color_texture_storage,
-                &mut projectile_target_storage,
-                &mut projectile_velocities_storage,
-                &mut camera,
-                &mesh_library,
-                &input_state,
-            );
-            UpdateProjectiles::exec(
-                &mut entities,
-                &mut position_storage,
-                &rotation_storage,
-                &projectile_target_storage,
-                &mut projectile_velocities_storage,
-                &frame_timing,
-            );
-            //rayon::join(
-            //|| {
-            ConsolidateMeshBuffers::exec(
-                &renderer,
-                &entities,
-                &graphics_command_pool,
-                &meshes_storage,
-                &image_index,
-                &mut consolidated_mesh_buffers,
-            );
-            //},
-            //|| {
-            //rayon::join(
-            //|| {
-            ModelMatrixCalculation::exec(
-                &entities,
-                &position_storage,
-                &rotation_storage,
-                &scale_storage,
-                &mut model_matrices_storage,
-            );
-            AABBCalculation::exec(
-                &entities,
-                &model_matrices_storage,
-                &meshes_storage,
-                &mut aabb_storage,
-            );
-            //},
-            //|| {
-            ShadowMappingMVPCalculation::exec(
-                &renderer,
-                &entities,
-                &position_storage,
-                &rotation_storage,
-                &mut shadow_mapping_light_matrices_storage,
-                &light_storage,
-                &image_index,
-                &main_descriptor_pool,
-                &camera_matrices,
-            );
-            //},
-            //)
-            //},
-            //);
-            // rayon::join(
-            // || {
-            // rayon::join(
-            // || {
-            CoarseCulling::exec(
-                &entities,
-                &aabb_storage,
-                &camera,
-                &mut coarse_culled_storage,
-            );
-            // },
-            // || {
-            SynchronizeBaseColorTextures::exec(
-                &entities,
-                &renderer,
-                &base_color_descriptor_set,
-                &base_color_texture_storage,
-                &image_index,
-                &mut base_color_visited_storage,
-            );
-            // },
-            // );
-            // },
-            // || {
-            // rayon::join(
-            CameraMatricesUpload::exec(&image_index, &camera, &mut camera_matrices);
-            //|| {
-            ModelMatricesUpload::exec(&model_matrices_storage, &image_index, &mut model_data);
-            //},
-            //);
-            //},
-            //);
-            // rayon::join(
-            // || {
-            CullPass::exec(
-                &entities,
-                &renderer,
-                &cull_pass_data,
-                &mut cull_pass_data_private,
-                &meshes_storage,
-                &image_index,
-                &consolidated_mesh_buffers,
-                &position_storage,
-                &camera,
-                &model_data,
-                &camera_matrices,
-            );
-            // },
-            // || {
-            PrepareShadowMaps::exec(
-                &entities,
-                &renderer,
-                &depth_pass_data,
-                &image_index,
-                &mut graphics_command_pool,
-                &mut shadow_mapping_data,
-                &meshes_storage,
-                &light_storage,
-                &shadow_mapping_light_matrices_storage,
-                &model_data,
-            );
-            DepthOnlyPass::exec(
-                &renderer,
-                &runtime_config,
-                &entities,
-                &image_index,
-                &meshes_storage,
-                &position_storage,
-                &camera,
-                &camera_matrices,
-                &mut depth_pass_data,
-                &swapchain,
-                &model_data,
-                &mut graphics_command_pool,
-            );
-            // },
-            // );
-            let gui_draw_data = gui.update(
-                &renderer,
-                &input_handler,
-                &swapchain,
-                &camera,
-                &mut runtime_config,
-            );
-            Renderer::exec(
-                &renderer,
-                &runtime_config,
-                &main_framebuffer,
-                &swapchain,
-                &entities,
-                &debug_aabb_pass_data,
-                &aabb_storage,
-                &mut gui_render,
-                &gui_draw_data,
-                &base_color_descriptor_set,
-                &consolidated_mesh_buffers,
-                &cull_pass_data,
-                &mut present_data,
-                &image_index,
-                &model_data,
-                &gltf_pass,
-                &mut graphics_command_pool,
-                &shadow_mapping_data,
-                &camera_matrices,
-            );
-            PresentFramebuffer::exec(&renderer, &present_data, &swapchain, &image_index);
+            world.maintain();
             {
-                let maintain_mask = entities.maintain();
-                meshes_storage.maintain(&maintain_mask);
-                position_storage.maintain(&maintain_mask);
-                rotation_storage.maintain(&maintain_mask);
-                scale_storage.maintain(&maintain_mask);
+                let renderer = world.read::<RenderFrame>();
+                // components of the entities freed above were last used by this frame
+                world.write::<Graveyard>().collect(
+                    renderer.frame_number * 16 + 15,
+                    renderer.graphics_timeline_semaphore.value().unwrap(),
+                );
             }
-            renderer.frame_number += 1;
+            let (save_snapshot, load_snapshot) = {
+                let mut runtime_config = world.write::<RuntimeConfiguration>();
+                let requests = (runtime_config.save_snapshot, runtime_config.load_snapshot);
+                runtime_config.save_snapshot = false;
+                runtime_config.load_snapshot = false;
+                requests
+            };
+            if save_snapshot {
+                Snapshot::capture(&world, &scene_loader).save(SNAPSHOT_PATH);
+            }
+            if load_snapshot {
+                let renderer = world.read::<RenderFrame>();
+                // removed meshes and textures may still be in use by the GPU
+                unsafe {
+                    renderer.device.device_wait_idle().unwrap();
+                }
+                let snapshot = Snapshot::open(SNAPSHOT_PATH);
+                snapshot.restore(&world);
+                snapshot.restore_assets(
+                    &renderer,
+                    &world.read::<GraphicsCommandPool>(),
+                    &world,
+                    &mut scene_loader,
+                );
+            }
+            world.write::<RenderFrame>().frame_number += 1;
         }
         if *quit_handle.lock() {
             unsafe {
-                renderer.device.device_wait_idle().unwrap();
+                world
+                    .read::<RenderFrame>()
+                    .device
+                    .device_wait_idle()
+                    .unwrap();
+            }
+            if let Some(ref path) = record_path {
+                input_recording.save(path);
+            }
+            if let Some(ref playback) = camera_path {
+                println!("Camera path: {}", playback.report());
+                playback.save_frame_times(CAMERA_PATH_TIMINGS_PATH);
             }
             break 'frame;
         }
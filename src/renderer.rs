@@ -11,6 +11,7 @@ mod systems {
     pub mod consolidate_mesh_buffers;
     pub mod cull_pipeline;
     pub mod debug_aabb_renderer;
+    pub mod debug_gizmo_renderer;
     pub mod present;
     pub mod shadow_mapping;
     pub mod textures;
@@ -30,8 +31,8 @@ pub use self::{
     gltf_mesh::{load as load_gltf, LoadedMesh},
     swapchain::*,
     systems::{
-        consolidate_mesh_buffers::*, cull_pipeline::*, debug_aabb_renderer::*, present::*,
-        shadow_mapping::*, textures::*,
+        consolidate_mesh_buffers::*, cull_pipeline::*, debug_aabb_renderer::*,
+        debug_gizmo_renderer::*, present::*, shadow_mapping::*, textures::*,
     },
 };
 
@@ -53,6 +54,7 @@ pub struct GltfMesh {
     pub index_buffers: Arc<Vec<(Buffer, u64)>>,
     pub vertex_len: u64,
     pub aabb: ncollide3d::bounding_volume::AABB<f32>,
+    pub collision_mesh: Arc<ncollide3d::shape::TriMesh<f32>>,
 }
 
 // TODO: rename
@@ -428,6 +430,8 @@ pub struct ModelData {
     pub model_set_layout: shaders::model_set::DescriptorSetLayout,
     pub model_set: DoubleBuffered<shaders::model_set::DescriptorSet>,
     pub model_buffer: DoubleBuffered<Buffer>,
+    /// Entities whose model matrix changed since each buffer was last written
+    pub model_buffer_stale: DoubleBuffered<croaring::Bitmap>,
 }
 
 impl ModelData {
@@ -458,6 +462,7 @@ impl ModelData {
             model_set_layout,
             model_set,
             model_buffer,
+            model_buffer_stale: renderer.new_buffered(|_| croaring::Bitmap::create()),
         }
     }
 }
@@ -574,7 +579,7 @@ impl DepthPassData {
                     &vk::PipelineDepthStencilStateCreateInfo::builder()
                         .depth_test_enable(true)
                         .depth_write_enable(true)
-                        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+                        .depth_compare_op(vk::CompareOp::GREATER_OR_EQUAL)
                         .depth_bounds_test_enable(false)
                         .max_depth_bounds(1.0)
                         .min_depth_bounds(0.0)
@@ -706,10 +711,10 @@ impl GltfPassData {
                         .front_face(vk::FrontFace::CLOCKWISE)
                         .line_width(1.0)
                         .polygon_mode(vk::PolygonMode::FILL)
-                        // magic
+                        // magic, positive to pull towards the camera with reversed depth
                         .depth_bias_enable(true)
-                        .depth_bias_constant_factor(-0.07)
-                        .depth_bias_slope_factor(-1.0)
+                        .depth_bias_constant_factor(0.07)
+                        .depth_bias_slope_factor(1.0)
                         .build(),
                 )
                 .multisample_state(
@@ -720,7 +725,7 @@ impl GltfPassData {
                 .depth_stencil_state(
                     &vk::PipelineDepthStencilStateCreateInfo::builder()
                         .depth_test_enable(true)
-                        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+                        .depth_compare_op(vk::CompareOp::GREATER_OR_EQUAL)
                         .depth_bounds_test_enable(false)
                         .max_depth_bounds(1.0)
                         .min_depth_bounds(0.0)
@@ -766,6 +771,9 @@ impl Renderer {
         entities: &EntitiesStorage,
         debug_aabb_pass_data: &DebugAABBPassData,
         aabbs: &ComponentStorage<ncollide3d::bounding_volume::AABB<f32>>,
+        selected: Option<Entity>,
+        debug_gizmo_pass_data: &DebugGizmoPassData,
+        gizmo_lines: &[crate::ecs::gizmo::GizmoLine],
         gui_render: &mut GuiRender,
         gui_draw_data: &imgui::DrawData,
         base_color_descriptor_set: &BaseColorDescriptorSet,
@@ -823,7 +831,7 @@ impl Renderer {
                     },
                     vk::ClearValue {
                         depth_stencil: vk::ClearDepthStencilValue {
-                            depth: 1.0,
+                            depth: 0.0,
                             stencil: 0,
                         },
                     },
@@ -873,17 +881,7 @@ impl Renderer {
                                 },
                             }],
                         );
-                        if runtime_config.debug_aabbs {
-                            DebugAABBPass::exec(
-                                &entities,
-                                &renderer,
-                                command_buffer,
-                                &debug_aabb_pass_data,
-                                &aabbs,
-                                &image_index,
-                                &camera_matrices,
-                            );
-                        } else {
+                        if !runtime_config.debug_aabbs {
                             renderer.device.debug_marker_around(
                                 command_buffer,
                                 "gltf meshes",
@@ -935,6 +933,25 @@ impl Renderer {
                                 },
                             );
                         }
+                        DebugAABBPass::exec(
+                            &entities,
+                            &renderer,
+                            command_buffer,
+                            &debug_aabb_pass_data,
+                            &aabbs,
+                            runtime_config.debug_aabbs,
+                            selected,
+                            &image_index,
+                            &camera_matrices,
+                        );
+                        DebugGizmoPass::exec(
+                            &renderer,
+                            command_buffer,
+                            &debug_gizmo_pass_data,
+                            gizmo_lines,
+                            &image_index,
+                            &camera_matrices,
+                        );
                         renderer.device.debug_marker_around(
                             command_buffer,
                             "GUI",
@@ -1352,9 +1369,20 @@ impl ModelMatricesUpload {
             .current_mut(image_index.0)
             .map::<glm::Mat4>()
             .expect("failed to map Model buffer");
-        for entity_id in model_matrices.mask().iter() {
+        assert_entity_ids_fit(
+            model_matrices.mask(),
+            shaders::MAX_MODEL_MATRICES,
+            "ModelMatrices.model",
+        );
+        for stale in model_data.model_buffer_stale.iter_mut() {
+            stale.or_inplace(model_matrices.modified());
+            stale.and_inplace(model_matrices.mask());
+        }
+        let stale = model_data.model_buffer_stale.current_mut(image_index.0);
+        for entity_id in stale.iter() {
             model_mapped[entity_id as usize] = *model_matrices.get(entity_id).unwrap();
         }
+        stale.clear();
     }
 }
 
@@ -1425,7 +1453,7 @@ impl DepthOnlyPass {
                                     })
                                     .clear_values(&[vk::ClearValue {
                                         depth_stencil: vk::ClearDepthStencilValue {
-                                            depth: 1.0,
+                                            depth: 0.0,
                                             stencil: 0,
                                         },
                                     }]),
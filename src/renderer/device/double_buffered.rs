@@ -23,4 +23,8 @@ impl<T> DoubleBuffered<T> {
     pub fn iter<'a>(&'a self) -> impl std::iter::Iterator<Item = &T> + 'a {
         self.data.iter()
     }
+
+    pub fn iter_mut<'a>(&'a mut self) -> impl std::iter::Iterator<Item = &mut T> + 'a {
+        self.data.iter_mut()
+    }
 }
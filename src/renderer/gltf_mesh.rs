@@ -17,6 +17,8 @@ pub struct LoadedMesh {
     pub index_buffers: Vec<(Buffer, u64)>,
     pub vertex_len: u64,
     pub aabb: ncollide3d::bounding_volume::AABB<f32>,
+    /// Full detail triangles in model space, for precise collisions
+    pub collision_mesh: ncollide3d::shape::TriMesh<f32>,
     pub base_color: Image,
 }
 
@@ -68,6 +70,23 @@ pub fn load(
         .expect("failed to load indices")
         .into_u32()
         .collect::<Vec<_>>();
+    let collision_mesh = ncollide3d::shape::TriMesh::new(
+        positions
+            .iter()
+            .map(|pos| na::Point3::from(pos.0))
+            .collect(),
+        indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                na::Point3::new(
+                    triangle[0] as usize,
+                    triangle[1] as usize,
+                    triangle[2] as usize,
+                )
+            })
+            .collect(),
+        None,
+    );
     let base_color_source = primitive
         .material()
         .pbr_metallic_roughness()
@@ -433,6 +452,7 @@ pub fn load(
         index_buffers,
         vertex_len,
         aabb,
+        collision_mesh,
         base_color: base_color_vkimage,
     }
 }
@@ -226,3 +226,16 @@ pub fn pick_lod<T>(lods: &[T], camera_pos: na::Point3<f32>, mesh_pos: na::Point3
         lods.first().expect("empty index buffer LODs")
     }
 }
+
+// Entity ids index straight into fixed-size GPU arrays, turn overflowing one into a readable panic
+pub fn assert_entity_ids_fit(mask: &croaring::Bitmap, capacity: usize, array: &str) {
+    if let Some(max_id) = mask.maximum() {
+        assert!(
+            (max_id as usize) < capacity,
+            "entity id {} does not fit in {}, which only has room for {} entities",
+            max_id,
+            array,
+            capacity
+        );
+    }
+}
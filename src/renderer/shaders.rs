@@ -431,8 +431,13 @@ macro_rules! make_pipe {
     };
 }
 
+// Entity ids index directly into these arrays, keep them in sync with the GLSL declarations
+pub const MAX_INDIRECT_COMMANDS: usize = 2400;
+pub const MAX_MODEL_MATRICES: usize = 4096;
+pub const MAX_BASE_COLOR_TEXTURES: u32 = 3072;
+
 pub struct IndirectCommands {
-    pub indirect_command: [vk::DrawIndexedIndirectCommand; 2400],
+    pub indirect_command: [vk::DrawIndexedIndirectCommand; MAX_INDIRECT_COMMANDS],
 }
 
 pub type OutIndexBuffer = [[u32; 3]; 20_000_000];
@@ -447,7 +452,7 @@ pub struct CameraMatrices {
 }
 
 pub struct ModelMatrices {
-    pub model: [glm::Mat4; 4096],
+    pub model: [glm::Mat4; MAX_MODEL_MATRICES],
 }
 
 pub type Null = ();
@@ -473,7 +478,7 @@ make_descriptor_set!(
 
 make_descriptor_set!(
     base_color_set [
-        3072, partially bound => texture, Null, vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+        super::MAX_BASE_COLOR_TEXTURES, partially bound => texture, Null, vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::COMBINED_IMAGE_SAMPLER
     ]
 );
 
@@ -531,6 +536,7 @@ make_pipe!(imgui_pipe {
 pub struct DebugAABBPushConstants {
     pub center: glm::Vec4,
     pub half_extent: glm::Vec4,
+    pub color: glm::Vec4,
 }
 
 make_pipe!(debug_aabb {
@@ -538,3 +544,16 @@ make_pipe!(debug_aabb {
     descriptors: [camera_set],
     push_constants: DebugAABBPushConstants
 });
+
+#[repr(C)]
+pub struct DebugGizmoPushConstants {
+    pub start: glm::Vec4,
+    pub end: glm::Vec4,
+    pub color: glm::Vec4,
+}
+
+make_pipe!(debug_gizmo {
+    vertex_inputs: [],
+    descriptors: [camera_set],
+    push_constants: DebugGizmoPushConstants
+});
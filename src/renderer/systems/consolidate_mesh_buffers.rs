@@ -7,24 +7,31 @@ use crate::{
         GltfMesh, GraphicsCommandPool, RenderFrame,
     },
 };
-use ash::{
-    version::DeviceV1_0,
-    vk::{self, Handle},
-};
+use ash::{version::DeviceV1_0, vk};
 use hashbrown::{hash_map::Entry, HashMap};
 #[cfg(feature = "microprofile")]
 use microprofile::scope;
-use std::{mem::size_of, u64};
+use std::{mem::size_of, sync::Arc, u64};
+
+/// Where a mesh was copied to in the shared buffers
+struct ConsolidatedMesh {
+    // keeps the source buffer alive, so that no other mesh can take over its address as the key
+    _vertex_buffer: Arc<Buffer>,
+    vertex_offset: vk::DeviceSize,
+    // one for every LOD, in the order of GltfMesh::index_buffers
+    index_offsets: Vec<vk::DeviceSize>,
+    // entities with a GltfMesh component using it
+    users: u32,
+}
 
 /// Describes layout of gltf mesh vertex data in a shared buffer
 pub struct ConsolidatedMeshBuffers {
-    /// Maps from vertex buffer handle to offset within the consolidated buffer
-    /// Hopefully this is distinct enough
-    pub vertex_offsets: HashMap<u64, vk::DeviceSize>,
+    /// Maps from the address of the vertex buffer of a mesh to its ranges in the shared buffers
+    meshes: HashMap<usize, ConsolidatedMesh>,
+    /// Mesh each entity was consolidated with, looked up again when the component is removed
+    entity_meshes: HashMap<u32, usize>,
     /// Next free vertex offset in the buffer that can be used for a new mesh
     next_vertex_offset: vk::DeviceSize,
-    /// Maps from index buffer handle to offset within the consolidated buffer
-    pub index_offsets: HashMap<u64, vk::DeviceSize>,
     /// Next free index offset in the buffer that can be used for a new mesh
     next_index_offset: vk::DeviceSize,
     /// Stores position data for each mesh
@@ -44,17 +51,14 @@ pub struct ConsolidatedMeshBuffers {
     sync_point_fence: Fence,
 }
 
-/// Identifies distinct GLTF meshes in components and copies them to a shared buffer
+/// Identifies distinct GLTF meshes in newly inserted components and copies them to a shared buffer
 pub struct ConsolidateMeshBuffers;
 
-// TODO: dynamic unloading of meshes
+// TODO: reuse the ranges of evicted meshes, they are only dropped from the lookup for now
 // TODO: use actual transfer queue for the transfers
 
 impl ConsolidatedMeshBuffers {
     pub fn new(renderer: &RenderFrame) -> ConsolidatedMeshBuffers {
-        let vertex_offsets = HashMap::new();
-        let index_offsets = HashMap::new();
-
         let position_buffer = renderer.device.new_buffer(
             vk::BufferUsageFlags::VERTEX_BUFFER
                 | vk::BufferUsageFlags::TRANSFER_DST
@@ -91,9 +95,9 @@ impl ConsolidatedMeshBuffers {
         );
 
         ConsolidatedMeshBuffers {
-            vertex_offsets,
+            meshes: HashMap::new(),
+            entity_meshes: HashMap::new(),
             next_vertex_offset: 0,
-            index_offsets,
             next_index_offset: 0,
             position_buffer,
             normal_buffer,
@@ -104,6 +108,17 @@ impl ConsolidatedMeshBuffers {
             sync_point_fence,
         }
     }
+
+    /// Vertex offset and the index offsets of every LOD of a consolidated mesh
+    pub fn offsets(&self, mesh: &GltfMesh) -> Option<(vk::DeviceSize, &[vk::DeviceSize])> {
+        self.meshes
+            .get(&mesh_key(mesh))
+            .map(|consolidated| (consolidated.vertex_offset, &consolidated.index_offsets[..]))
+    }
+}
+
+fn mesh_key(mesh: &GltfMesh) -> usize {
+    &*mesh.vertex_buffer as *const Buffer as usize
 }
 
 impl ConsolidateMeshBuffers {
@@ -139,11 +154,25 @@ impl ConsolidateMeshBuffers {
                 .expect("failed to reset consolidate vertex buffers sync point fence");
         }
 
+        // release the meshes of removed components first, replaced ones are inserted again below
+        for entity_id in meshes.removed().iter() {
+            let key = match consolidated_mesh_buffers.entity_meshes.remove(&entity_id) {
+                Some(key) => key,
+                None => continue,
+            };
+            if let Entry::Occupied(mut consolidated) = consolidated_mesh_buffers.meshes.entry(key) {
+                consolidated.get_mut().users -= 1;
+                if consolidated.get().users == 0 {
+                    consolidated.remove();
+                }
+            }
+        }
+
         let mut needs_transfer = false;
         let command_buffer = graphics_command_pool.0.record_one_time(
             "consolidate mesh buffers cb",
             |command_buffer| {
-                for ix in (entities.mask() & meshes.mask()).iter() {
+                for ix in (entities.mask() & meshes.inserted()).iter() {
                     let mesh = meshes.get(ix).unwrap();
                     let ConsolidatedMeshBuffers {
                         ref mut next_vertex_offset,
@@ -152,81 +181,88 @@ impl ConsolidateMeshBuffers {
                         ref normal_buffer,
                         ref uv_buffer,
                         ref index_buffer,
-                        ref mut vertex_offsets,
-                        ref mut index_offsets,
+                        meshes: ref mut consolidated_meshes,
+                        ref mut entity_meshes,
                         ..
                     } = *consolidated_mesh_buffers;
 
-                    if let Entry::Vacant(v) =
-                        vertex_offsets.entry(mesh.vertex_buffer.handle.as_raw())
-                    {
-                        v.insert(*next_vertex_offset);
-                        let size_3 = mesh.vertex_len * size_of::<[f32; 3]>() as vk::DeviceSize;
-                        let size_2 = mesh.vertex_len * size_of::<[f32; 2]>() as vk::DeviceSize;
-                        let offset_3 =
-                            *next_vertex_offset * size_of::<[f32; 3]>() as vk::DeviceSize;
-                        let offset_2 =
-                            *next_vertex_offset * size_of::<[f32; 2]>() as vk::DeviceSize;
+                    let key = mesh_key(mesh);
+                    entity_meshes.insert(ix, key);
+                    let slot = match consolidated_meshes.entry(key) {
+                        Entry::Occupied(mut consolidated) => {
+                            consolidated.get_mut().users += 1;
+                            continue;
+                        }
+                        Entry::Vacant(slot) => slot,
+                    };
+
+                    let vertex_offset = *next_vertex_offset;
+                    let size_3 = mesh.vertex_len * size_of::<[f32; 3]>() as vk::DeviceSize;
+                    let size_2 = mesh.vertex_len * size_of::<[f32; 2]>() as vk::DeviceSize;
+                    let offset_3 = vertex_offset * size_of::<[f32; 3]>() as vk::DeviceSize;
+                    let offset_2 = vertex_offset * size_of::<[f32; 2]>() as vk::DeviceSize;
+
+                    unsafe {
+                        // vertex
+                        renderer.device.cmd_copy_buffer(
+                            command_buffer,
+                            mesh.vertex_buffer.handle,
+                            position_buffer.handle,
+                            &[vk::BufferCopy::builder()
+                                .size(size_3)
+                                .dst_offset(offset_3)
+                                .build()],
+                        );
+                        // normal
+                        renderer.device.cmd_copy_buffer(
+                            command_buffer,
+                            mesh.normal_buffer.handle,
+                            normal_buffer.handle,
+                            &[vk::BufferCopy::builder()
+                                .size(size_3)
+                                .dst_offset(offset_3)
+                                .build()],
+                        );
+                        // uv
+                        renderer.device.cmd_copy_buffer(
+                            command_buffer,
+                            mesh.uv_buffer.handle,
+                            uv_buffer.handle,
+                            &[vk::BufferCopy::builder()
+                                .size(size_2)
+                                .dst_offset(offset_2)
+                                .build()],
+                        );
+                    }
+                    *next_vertex_offset += mesh.vertex_len;
+
+                    let mut index_offsets = vec![];
+                    for (lod_index_buffer, index_len) in mesh.index_buffers.iter() {
+                        index_offsets.push(*next_index_offset);
 
                         unsafe {
-                            // vertex
-                            renderer.device.cmd_copy_buffer(
-                                command_buffer,
-                                mesh.vertex_buffer.handle,
-                                position_buffer.handle,
-                                &[vk::BufferCopy::builder()
-                                    .size(size_3)
-                                    .dst_offset(offset_3)
-                                    .build()],
-                            );
-                            // normal
-                            renderer.device.cmd_copy_buffer(
-                                command_buffer,
-                                mesh.normal_buffer.handle,
-                                normal_buffer.handle,
-                                &[vk::BufferCopy::builder()
-                                    .size(size_3)
-                                    .dst_offset(offset_3)
-                                    .build()],
-                            );
-                            // uv
                             renderer.device.cmd_copy_buffer(
                                 command_buffer,
-                                mesh.uv_buffer.handle,
-                                uv_buffer.handle,
+                                lod_index_buffer.handle,
+                                index_buffer.handle,
                                 &[vk::BufferCopy::builder()
-                                    .size(size_2)
-                                    .dst_offset(offset_2)
+                                    .size(index_len * size_of::<u32>() as vk::DeviceSize)
+                                    .dst_offset(
+                                        *next_index_offset * size_of::<u32>() as vk::DeviceSize,
+                                    )
                                     .build()],
                             );
                         }
-                        *next_vertex_offset += mesh.vertex_len;
-                        needs_transfer = true;
+                        *next_index_offset += index_len;
                     }
 
-                    for (lod_index_buffer, index_len) in mesh.index_buffers.iter() {
-                        if let Entry::Vacant(v) =
-                            index_offsets.entry(lod_index_buffer.handle.as_raw())
-                        {
-                            v.insert(*next_index_offset);
-
-                            unsafe {
-                                renderer.device.cmd_copy_buffer(
-                                    command_buffer,
-                                    lod_index_buffer.handle,
-                                    index_buffer.handle,
-                                    &[vk::BufferCopy::builder()
-                                        .size(index_len * size_of::<u32>() as vk::DeviceSize)
-                                        .dst_offset(
-                                            *next_index_offset * size_of::<u32>() as vk::DeviceSize,
-                                        )
-                                        .build()],
-                                );
-                            }
-                            *next_index_offset += index_len;
-                            needs_transfer = true;
-                        }
-                    }
+                    slot.insert(ConsolidatedMesh {
+                        _vertex_buffer: Arc::clone(&mesh.vertex_buffer),
+                        vertex_offset,
+                        index_offsets,
+                        users: 1,
+                    });
+                    needs_transfer = true;
                 }
             },
         );
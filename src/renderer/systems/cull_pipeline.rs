@@ -8,15 +8,13 @@ use super::{
     consolidate_mesh_buffers::ConsolidatedMeshBuffers,
     present::ImageIndex,
 };
-use crate::ecs::{custom::*, systems::Camera};
-use ash::{
-    version::DeviceV1_0,
-    vk::{self, Handle},
-};
+use crate::ecs::{custom::*, spatial::SpatialIndex, systems::Camera};
+use ash::{version::DeviceV1_0, vk};
 #[cfg(feature = "microprofile")]
 use microprofile::scope;
 use num_traits::ToPrimitive;
 use parking_lot::Mutex;
+use rayon::prelude::*;
 use std::{cmp::min, path::PathBuf, sync::Arc, u64};
 
 // Cull geometry in compute pass
@@ -48,27 +46,25 @@ pub struct CullPassEvent {
 }
 
 impl CoarseCulling {
+    /// Everything is culled except the entities found in the frustum by the `SpatialIndex`,
+    /// which skips whole subtrees that are outside
     pub fn exec(
         entities: &EntitiesStorage,
         aabbs: &ComponentStorage<ncollide3d::bounding_volume::AABB<f32>>,
+        spatial_index: &SpatialIndex,
         camera: &Camera,
         coarse_culled: &mut ComponentStorage<CoarseCulled>,
     ) {
         #[cfg(feature = "profiling")]
         microprofile::scope!("ecs", "coarse culling");
-        for entity_id in (entities.mask() & aabbs.mask()).iter() {
-            let aabb = aabbs.get(entity_id).unwrap();
-            let mut outside = false;
-            'per_plane: for plane in camera.frustum_planes.iter() {
-                let e = aabb.half_extents().dot(&plane.xyz().abs());
-
-                let s = plane.dot(&aabb.center().to_homogeneous());
-                if s - e > 0.0 {
-                    outside = true;
-                    break 'per_plane;
-                }
+        coarse_culled.replace_mask_with(&(entities, aabbs).join_mask(), || CoarseCulled(true));
+        par_join((&mut *coarse_culled,)).for_each(|(coarse_culled,)| {
+            coarse_culled.0 = true;
+        });
+        for entity in spatial_index.frustum(&camera.frustum_planes) {
+            if coarse_culled.mask().contains(entity.index) {
+                coarse_culled.get_mut(entity).unwrap().0 = false;
             }
-            coarse_culled.insert(entity_id, CoarseCulled(outside));
         }
     }
 }
@@ -220,6 +216,13 @@ impl CullPass {
             .current(image_index.0)
             .update_whole_buffer(&renderer, 3, &consolidate_mesh_buffers.index_buffer);
 
+        let to_cull = entities.mask() & meshes.mask() & positions.mask();
+        helpers::assert_entity_ids_fit(
+            &to_cull,
+            super::super::shaders::MAX_INDIRECT_COMMANDS,
+            "IndirectCommands.indirect_command",
+        );
+
         let mut index_offset_in_output = 0i32;
 
         let cull_cb = renderer.compute_command_pool.record_one_time(
@@ -269,20 +272,17 @@ impl CullPass {
                             &camera_matrices.set.current(image_index.0),
                             &cull_pass_data.cull_set.current(image_index.0),
                         );
-                        for entity_id in (entities.mask() & meshes.mask() & positions.mask()).iter()
-                        {
+                        for entity_id in to_cull.iter() {
                             let mesh = meshes.get(entity_id).unwrap();
                             let mesh_position = positions.get(entity_id).unwrap();
-                            let vertex_offset = consolidate_mesh_buffers
-                                .vertex_offsets
-                                .get(&mesh.vertex_buffer.handle.as_raw())
-                                .expect("Vertex buffer not consolidated");
-                            let (index_buffer, index_len) =
+                            let (vertex_offset, index_offsets) = consolidate_mesh_buffers
+                                .offsets(mesh)
+                                .expect("Mesh not consolidated");
+                            // the offsets line up with the LODs, so both pick the same one
+                            let (_, index_len) =
                                 pick_lod(&mesh.index_buffers, camera.position, *mesh_position);
-                            let index_offset = consolidate_mesh_buffers
-                                .index_offsets
-                                .get(&index_buffer.handle.as_raw())
-                                .expect("Index buffer not consolidated");
+                            let index_offset =
+                                pick_lod(index_offsets, camera.position, *mesh_position);
 
                             let push_constants = super::super::shaders::GenerateWorkPushConstants {
                                 gltf_index: entity_id,
@@ -11,7 +11,7 @@ use ash::{version::DeviceV1_0, vk};
 use microprofile::scope;
 use std::{path::PathBuf, sync::Arc};
 
-// Render AABB outlines
+// Render AABB outlines, of all entities in debug mode and of the selected one
 pub struct DebugAABBPass;
 
 pub struct DebugAABBPassData {
@@ -76,7 +76,7 @@ impl DebugAABBPassData {
                     &vk::PipelineDepthStencilStateCreateInfo::builder()
                         .depth_test_enable(true)
                         .depth_write_enable(true)
-                        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+                        .depth_compare_op(vk::CompareOp::GREATER_OR_EQUAL)
                         .depth_bounds_test_enable(false)
                         .max_depth_bounds(1.0)
                         .min_depth_bounds(0.0)
@@ -117,6 +117,8 @@ impl DebugAABBPass {
         command_buffer: vk::CommandBuffer,
         debug_aabb_pass_data: &DebugAABBPassData,
         aabbs: &ComponentStorage<ncollide3d::bounding_volume::AABB<f32>>,
+        debug_aabbs: bool,
+        selected: Option<Entity>,
         image_index: &ImageIndex,
         camera_matrices: &CameraMatrices,
     ) {
@@ -141,7 +143,7 @@ impl DebugAABBPass {
                     &camera_matrices.set.current(image_index.0),
                 );
 
-                for entity_id in (entities.mask() & aabbs.mask()).iter() {
+                let draw = |entity_id: u32, color: glm::Vec4| {
                     let aabb = aabbs.get(entity_id).unwrap();
                     debug_aabb_pass_data.pipeline_layout.push_constants(
                         &renderer.device,
@@ -149,11 +151,23 @@ impl DebugAABBPass {
                         &shaders::DebugAABBPushConstants {
                             center: aabb.center().to_homogeneous(),
                             half_extent: aabb.half_extents().push(1.0),
+                            color,
                         },
                     );
                     unsafe {
                         renderer.device.cmd_draw(command_buffer, 36, 1, 0, 0);
                     }
+                };
+
+                if debug_aabbs {
+                    for entity_id in (entities.mask() & aabbs.mask()).iter() {
+                        draw(entity_id, glm::vec4(0.7, 0.7, 0.7, 1.0));
+                    }
+                }
+                if let Some(entity) = selected {
+                    if entities.is_alive(entity) && aabbs.mask().contains(entity.index) {
+                        draw(entity.index, glm::vec4(1.0, 0.6, 0.0, 1.0));
+                    }
                 }
             },
         );
@@ -0,0 +1,161 @@
+use super::{
+    super::{
+        helpers::{self, Pipeline},
+        shaders, CameraMatrices, RenderFrame,
+    },
+    present::ImageIndex,
+};
+use crate::ecs::gizmo::GizmoLine;
+use ash::{version::DeviceV1_0, vk};
+#[cfg(feature = "microprofile")]
+use microprofile::scope;
+use std::{path::PathBuf, sync::Arc};
+
+// Render the gizmo of the selected entity on top of everything
+pub struct DebugGizmoPass;
+
+pub struct DebugGizmoPassData {
+    pub pipeline_layout: shaders::debug_gizmo::PipelineLayout,
+    pub pipeline: Pipeline,
+}
+
+impl DebugGizmoPassData {
+    pub fn new(renderer: &RenderFrame, camera_matrices: &CameraMatrices) -> DebugGizmoPassData {
+        let device = &renderer.device;
+
+        let pipeline_layout =
+            shaders::debug_gizmo::PipelineLayout::new(&device, &camera_matrices.set_layout);
+        use std::io::Read;
+        let path = std::path::PathBuf::from(env!("OUT_DIR")).join("debug_gizmo.vert.spv");
+        let file = std::fs::File::open(path).expect("Could not find shader.");
+        let bytes: Vec<u8> = file.bytes().filter_map(Result::ok).collect();
+        let module = spirv_reflect::create_shader_module(&bytes).unwrap();
+        debug_assert!(shaders::debug_gizmo::verify_spirv(&module));
+        let pipeline = helpers::new_graphics_pipeline2(
+            Arc::clone(&renderer.device),
+            &[
+                (
+                    vk::ShaderStageFlags::VERTEX,
+                    PathBuf::from(env!("OUT_DIR")).join("debug_gizmo.vert.spv"),
+                ),
+                (
+                    vk::ShaderStageFlags::FRAGMENT,
+                    PathBuf::from(env!("OUT_DIR")).join("debug_gizmo.frag.spv"),
+                ),
+            ],
+            vk::GraphicsPipelineCreateInfo::builder()
+                .vertex_input_state(&shaders::debug_gizmo::vertex_input_state())
+                .input_assembly_state(
+                    &vk::PipelineInputAssemblyStateCreateInfo::builder()
+                        .topology(vk::PrimitiveTopology::LINE_LIST),
+                )
+                .dynamic_state(
+                    &vk::PipelineDynamicStateCreateInfo::builder()
+                        .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]),
+                )
+                .viewport_state(
+                    &vk::PipelineViewportStateCreateInfo::builder()
+                        .viewport_count(1)
+                        .scissor_count(1)
+                        .build(),
+                )
+                .rasterization_state(
+                    &vk::PipelineRasterizationStateCreateInfo::builder()
+                        .cull_mode(vk::CullModeFlags::NONE)
+                        .line_width(1.0)
+                        .polygon_mode(vk::PolygonMode::FILL)
+                        .build(),
+                )
+                .multisample_state(
+                    &vk::PipelineMultisampleStateCreateInfo::builder()
+                        .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+                        .build(),
+                )
+                .depth_stencil_state(
+                    &vk::PipelineDepthStencilStateCreateInfo::builder()
+                        .depth_test_enable(false)
+                        .depth_write_enable(false)
+                        .depth_compare_op(vk::CompareOp::GREATER_OR_EQUAL)
+                        .depth_bounds_test_enable(false)
+                        .max_depth_bounds(1.0)
+                        .min_depth_bounds(0.0)
+                        .build(),
+                )
+                .color_blend_state(
+                    &vk::PipelineColorBlendStateCreateInfo::builder()
+                        .attachments(&[vk::PipelineColorBlendAttachmentState::builder()
+                            .blend_enable(true)
+                            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+                            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                            .color_blend_op(vk::BlendOp::ADD)
+                            .src_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                            .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+                            .alpha_blend_op(vk::BlendOp::ADD)
+                            .color_write_mask(vk::ColorComponentFlags::all())
+                            .build()])
+                        .build(),
+                )
+                .layout(pipeline_layout.layout.handle)
+                .render_pass(renderer.renderpass.handle)
+                .subpass(0)
+                .build(),
+        );
+
+        DebugGizmoPassData {
+            pipeline,
+            pipeline_layout,
+        }
+    }
+}
+
+impl DebugGizmoPass {
+    pub fn exec(
+        renderer: &RenderFrame,
+        command_buffer: vk::CommandBuffer,
+        debug_gizmo_pass_data: &DebugGizmoPassData,
+        lines: &[GizmoLine],
+        image_index: &ImageIndex,
+        camera_matrices: &CameraMatrices,
+    ) {
+        #[cfg(feature = "profiling")]
+        microprofile::scope!("ecs", "debug gizmo pass");
+
+        if lines.is_empty() {
+            return;
+        }
+        renderer.device.debug_marker_around(
+            command_buffer,
+            "gizmo debug",
+            [1.0, 1.0, 0.0, 1.0],
+            || {
+                unsafe {
+                    renderer.device.cmd_bind_pipeline(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        debug_gizmo_pass_data.pipeline.handle,
+                    );
+                }
+                debug_gizmo_pass_data.pipeline_layout.bind_descriptor_sets(
+                    &renderer.device,
+                    command_buffer,
+                    &camera_matrices.set.current(image_index.0),
+                );
+
+                for line in lines {
+                    debug_gizmo_pass_data.pipeline_layout.push_constants(
+                        &renderer.device,
+                        command_buffer,
+                        &shaders::DebugGizmoPushConstants {
+                            start: line.start.to_homogeneous(),
+                            end: line.end.to_homogeneous(),
+                            color: line.color,
+                        },
+                    );
+                    unsafe {
+                        renderer.device.cmd_draw(command_buffer, 2, 1, 0, 0);
+                    }
+                }
+            },
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::ecs::{components::*, custom::*};
+use crate::ecs::{components::*, custom::*, hierarchy::parent_matrix};
 use crate::renderer::*;
 use ash::vk;
 
@@ -258,6 +258,8 @@ impl ShadowMappingData {
 pub struct ShadowMappingLightMatrices {
     matrices_set: DoubleBuffered<super::super::shaders::camera_set::DescriptorSet>,
     matrices_buffer: DoubleBuffered<Buffer>,
+    // buffers still holding matrices from before the light last moved
+    stale_buffers: DoubleBuffered<bool>,
 }
 
 #[repr(C)]
@@ -277,20 +279,32 @@ impl ShadowMappingMVPCalculation {
         entities: &EntitiesStorage,
         positions: &ComponentStorage<na::Point3<f32>>,
         rotations: &ComponentStorage<na::UnitQuaternion<f32>>,
+        parents: &ComponentStorage<Parent>,
+        model_matrices: &ComponentStorage<glm::Mat4>,
         light_matrices: &mut ComponentStorage<ShadowMappingLightMatrices>,
         lights: &ComponentStorage<Light>,
         image_index: &ImageIndex,
         main_descriptor_pool: &MainDescriptorPool,
         camera_matrices: &CameraMatrices,
+        graveyard: &mut Graveyard,
     ) {
         debug_assert_eq!(size_of::<LightMatrices>(), 144);
         #[cfg(feature = "profiling")]
         microprofile::scope!("ecs", "shadow mapping light matrices calculation");
-        let desired = entities.mask() & positions.mask() & lights.mask() & rotations.mask();
-        light_matrices.replace_mask(&desired);
-        for entity_id in desired.iter() {
-            let light_position = positions.get(entity_id).unwrap();
-            let light_rotation = rotations.get(entity_id).unwrap();
+        // new and replaced lights need their matrices uploaded as well
+        let moved = croaring::Bitmap::fast_or(&[
+            positions.modified(),
+            rotations.modified(),
+            lights.inserted(),
+            parents.modified(),
+            parents.removed(),
+        ]);
+        let joined = join((entities, positions, rotations, lights));
+        // the buffers of removed lights may still be read by the previous frame
+        light_matrices.replace_mask_taking(joined.mask(), |matrices| graveyard.bury(matrices));
+        for (entity_id, light_position, light_rotation, _light) in joined {
+            // lights attached to another entity follow its model matrix
+            let parent_matrix = parent_matrix(entities, parents, model_matrices, entity_id);
             let light_matrix = light_matrices.entry(entity_id).or_insert_with(|| {
                 let matrices_buffer = renderer.new_buffered(|ix| {
                     let b = renderer.device.new_buffer(
@@ -323,8 +337,23 @@ impl ShadowMappingMVPCalculation {
                 ShadowMappingLightMatrices {
                     matrices_buffer,
                     matrices_set,
+                    stale_buffers: renderer.new_buffered(|_| true),
                 }
             });
+            if moved.contains(entity_id)
+                || parent_matrix.map_or(false, |(parent, _)| {
+                    model_matrices.modified().contains(parent)
+                })
+            {
+                for stale in light_matrix.stale_buffers.iter_mut() {
+                    *stale = true;
+                }
+            }
+            let stale = light_matrix.stale_buffers.current_mut(image_index.0);
+            if !*stale {
+                continue;
+            }
+            *stale = false;
             let near = 10.0;
             let far = 400.0;
             let projection =
@@ -332,6 +361,13 @@ impl ShadowMappingMVPCalculation {
 
             let view = glm::translation(&(light_rotation * (-light_position.coords)))
                 * light_rotation.to_homogeneous();
+            let (view, light_position) = match parent_matrix {
+                Some((_, parent_matrix)) => (
+                    view * glm::inverse(parent_matrix),
+                    parent_matrix.transform_point(light_position),
+                ),
+                None => (view, *light_position),
+            };
             let mut matrices_mapped = light_matrix
                 .matrices_buffer
                 .current_mut(image_index.0)
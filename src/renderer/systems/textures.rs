@@ -74,9 +74,43 @@ impl SynchronizeBaseColorTextures {
         image_index: &ImageIndex,
         visited_markers: &mut ComponentStorage<BaseColorVisitedMarker>,
     ) {
-        let to_update = (entities.mask() & base_color_textures.mask()) - visited_markers.mask();
+        let mut counter: u64 = 0;
+        assert_eq!(
+            vk::Result::SUCCESS,
+            (renderer.device.get_semaphore_counter_value)(
+                renderer.device.handle(),
+                renderer.graphics_timeline_semaphore.handle,
+                &mut counter
+            ),
+            "Get semaphore counter value failed",
+        );
+        dbg!(counter, renderer.frame_number, renderer.frame_number * 16);
+
+        // wait on last frame completion, it may still read the descriptor set updated below
+        // and the image views of textures removed since. Markers of despawned entities were
+        // already handed to the Graveyard by World::maintain(), so they are not dropped here.
+        let wait_ix = renderer.frame_number * 16;
+        let wait_ixes = &[wait_ix];
+        let wait_semaphores = &[renderer.graphics_timeline_semaphore.handle];
+        let wait_info = vk::SemaphoreWaitInfo::builder()
+            .semaphores(wait_semaphores)
+            .values(wait_ixes);
+        assert_eq!(
+            vk::Result::SUCCESS,
+            (renderer.device.wait_semaphores)(renderer.device.handle(), &*wait_info, std::u64::MAX),
+            "Wait for ix {} failed.",
+            wait_ix
+        );
 
-        visited_markers.replace_mask(&(visited_markers.mask() | &to_update));
+        for entity_id in base_color_textures.removed().iter() {
+            visited_markers.entry(entity_id).remove();
+        }
+        let to_update = entities.mask() & base_color_textures.inserted();
+        helpers::assert_entity_ids_fit(
+            &to_update,
+            super::super::shaders::MAX_BASE_COLOR_TEXTURES as usize,
+            "base_color_set.texture",
+        );
 
         for entity_id in to_update.iter() {
             let base_color = base_color_textures.get(entity_id).unwrap();
@@ -106,32 +140,6 @@ impl SynchronizeBaseColorTextures {
             assert!(res.is_none()); // double check that there was nothing there
         }
 
-        let mut counter: u64 = 0;
-        assert_eq!(
-            vk::Result::SUCCESS,
-            (renderer.device.get_semaphore_counter_value)(
-                renderer.device.handle(),
-                renderer.graphics_timeline_semaphore.handle,
-                &mut counter
-            ),
-            "Get semaphore counter value failed",
-        );
-        dbg!(counter, renderer.frame_number, renderer.frame_number * 16);
-
-        // wait on last frame completion
-        let wait_ix = renderer.frame_number * 16;
-        let wait_ixes = &[wait_ix];
-        let wait_semaphores = &[renderer.graphics_timeline_semaphore.handle];
-        let wait_info = vk::SemaphoreWaitInfo::builder()
-            .semaphores(wait_semaphores)
-            .values(wait_ixes);
-        assert_eq!(
-            vk::Result::SUCCESS,
-            (renderer.device.wait_semaphores)(renderer.device.handle(), &*wait_info, std::u64::MAX),
-            "Wait for ix {} failed.",
-            wait_ix
-        );
-
         // unsafe { renderer.device.device_wait_idle().unwrap(); }
 
         for entity_id in visited_markers.mask().iter() {
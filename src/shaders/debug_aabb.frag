@@ -1,7 +1,13 @@
 #version 450
 
+layout(push_constant) uniform PushConstants {
+    vec4 center;
+    vec4 half_extent;
+    vec4 color;
+};
+
 layout (location = 0) out vec4 o_color;
 
 void main() {
-    o_color = vec4(0.7, 0.7, 0.7, 1.0);
+    o_color = color;
 }
@@ -3,6 +3,7 @@
 layout(push_constant) uniform PushConstants {
     vec4 center;
     vec4 half_extent;
+    vec4 color;
 };
 
 layout(set = 0, binding = 0) uniform CameraMatrices {
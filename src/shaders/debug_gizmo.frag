@@ -0,0 +1,13 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+    vec4 start;
+    vec4 end;
+    vec4 color;
+};
+
+layout (location = 0) out vec4 o_color;
+
+void main() {
+    o_color = color;
+}
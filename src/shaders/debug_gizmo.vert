@@ -0,0 +1,19 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+    vec4 start;
+    vec4 end;
+    vec4 color;
+};
+
+layout(set = 0, binding = 0) uniform CameraMatrices {
+    mat4 projection;
+    mat4 view;
+    vec4 pos;
+};
+
+void main() {
+    vec4 position = gl_VertexIndex == 0 ? start : end;
+
+    gl_Position = projection * view * position;
+}
@@ -21,6 +21,8 @@ layout (location = 0) out vec4 o_color;
 
 void main() {
     o_color = texture(base_color[entity_id], uv);
+    // interpolation shortens normals
+    vec3 normal = normalize(normal);
 
     for (uint ix = 0; ix < 2; ix++) {
         // NOTE: Order of these next few operations around light_pos is critical
@@ -28,7 +28,8 @@ layout (location = 4) out vec4 position_lightspace[2];
 void main() {
     uint entity_id = gl_InstanceIndex;
     // https://paroj.github.io/gltut/Illumination/Tut09%20Normal%20Transformation.html
-    o_normal = transpose(inverse(mat3(model[entity_id]))) * normal;
+    // the inverse transpose keeps normals perpendicular under non-uniform scale, but not unit length
+    o_normal = normalize(transpose(inverse(mat3(model[entity_id]))) * normal);
     o_world_pos = vec3(model[entity_id] * vec4(position, 1.0));
     gl_Position = camera.projection * camera.view * vec4(o_world_pos, 1.0);
     o_uv = uv;
@@ -36,7 +37,7 @@ void main() {
     for (uint ix = 0; ix < 2; ix++) {
         // http://www.dissidentlogic.com/old/images/NormalOffsetShadows/GDC_Poster_NormalOffset.png
         vec3 to_light = normalize(light_data[ix].position.xyz - o_world_pos);
-        float cos_light = dot(to_light, normal);
+        float cos_light = dot(to_light, o_normal);
         float slope_scale = clamp(1 - cos_light, 0.0, 1.0);
         // TODO: tweak these
         float normal_offset = -1.;